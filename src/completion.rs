@@ -0,0 +1,336 @@
+use crate::core::{assign_args, follow_path};
+use crate::parser::{Menus, Node, VarDef, VarType};
+use crate::shell_init::InitShell;
+
+/// A value the current word can be completed to
+#[derive(Debug, PartialEq, Eq)]
+pub struct Candidate {
+    pub value: String,
+    pub description: Option<String>,
+}
+
+impl std::fmt::Display for Candidate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.description {
+            Some(description) => write!(f, "{}\t{description}", self.value),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+/// Returns the script that sets up completions for `shell`. It calls `dt __complete` with the
+/// words of the command line, which prints the candidates, one per line, with the value and the
+/// description separated by a tab
+pub fn script(shell: InitShell) -> &'static str {
+    match shell {
+        InitShell::Bash => BASH,
+        InitShell::Zsh => ZSH,
+        InitShell::Fish => FISH,
+    }
+}
+
+/// Completes `current`, the word after `input`, which are the keys and the args that were
+/// already entered. Before the keys of a command are complete, the keys of the next entries are
+/// completed, afterwards the names and the choices of its vars
+pub fn candidates(menus: &Menus, input: &[String], current: &str) -> Vec<Candidate> {
+    match input.split_first() {
+        None => key_candidates(menus, current),
+        Some((keys, args)) => {
+            let chars: Vec<_> = keys.chars().collect();
+            let root_node = Node::Menu(menus.root());
+            match follow_path(menus, &root_node, &chars, 0) {
+                (Some(Node::Command(cmd)), pos) if pos == chars.len() => {
+                    var_candidates(&cmd.env_vars, args, current)
+                }
+                _ => vec![],
+            }
+        }
+    }
+}
+
+fn key_candidates(menus: &Menus, current: &str) -> Vec<Candidate> {
+    let chars: Vec<_> = current.chars().collect();
+    let root_node = Node::Menu(menus.root());
+    let (node, pos) = match follow_path(menus, &root_node, &chars, 0) {
+        (Some(node), pos) => (node, pos),
+        (None, _) => return vec![],
+    };
+    let id = match node {
+        Node::Menu(id) => *id,
+        Node::Command(_) if pos < chars.len() => return vec![],
+        Node::Command(_) => {
+            return vec![Candidate {
+                value: current.to_string(),
+                description: Some(node.display(menus).to_string()),
+            }]
+        }
+    };
+    let prefix = String::from_iter(&chars[..pos]);
+    let remaining = &chars[pos..];
+    let matching: Vec<_> = menus[id]
+        .sorted_entries(menus)
+        .into_iter()
+        .filter(|(keys, _)| keys.starts_with(remaining))
+        .collect();
+    if let [(keys, Node::Menu(_))] = matching.as_slice() {
+        // the only way to go is into the menu, so its entries are what can be completed
+        return key_candidates(menus, &format!("{prefix}{}", String::from_iter(keys)));
+    }
+    matching
+        .into_iter()
+        .map(|(keys, node)| Candidate {
+            value: format!("{prefix}{}", String::from_iter(keys)),
+            description: Some(node.display(menus).to_string()),
+        })
+        .collect()
+}
+
+fn var_candidates(vars: &[VarDef], args: &[String], current: &str) -> Vec<Candidate> {
+    // everything after -- is passed on to the command
+    if args.iter().any(|a| a == "--") {
+        return vec![];
+    }
+    let Ok(assigned) = assign_args(vars, args) else {
+        // e.g. the value of a named arg is completed
+        return match args.last().and_then(|a| a.strip_prefix("--")) {
+            Some(name) => vars
+                .iter()
+                .find(|v| v.name == name.replace('-', "_"))
+                .map(|var| value_candidates(var, current))
+                .unwrap_or_default(),
+            None => vec![],
+        };
+    };
+    if current.starts_with("--") {
+        return vars
+            .iter()
+            .zip(&assigned)
+            .filter(|(var, val)| val.is_none() && format!("--{}", var.name).starts_with(current))
+            .map(|(var, _)| Candidate {
+                value: format!("--{}", var.name),
+                description: var.prompt.clone(),
+            })
+            .collect();
+    }
+    match vars.iter().zip(&assigned).find(|(_, val)| val.is_none()) {
+        Some((var, _)) => value_candidates(var, current),
+        None => vec![],
+    }
+}
+
+fn value_candidates(var: &VarDef, current: &str) -> Vec<Candidate> {
+    let values = match &var.var_type {
+        VarType::Choice(choices) => choices.clone(),
+        VarType::Bool => vec!["true".to_string(), "false".to_string()],
+        _ => vec![],
+    };
+    values
+        .into_iter()
+        .filter(|v| v.starts_with(current))
+        .map(|value| Candidate {
+            value,
+            description: None,
+        })
+        .collect()
+}
+
+const BASH: &str = r#"_dt() {
+    local IFS=$'\n'
+    local candidates=($(dt __complete -- "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null))
+    COMPREPLY=()
+    if [ ${#candidates[@]} -eq 1 ]; then
+        COMPREPLY=("${candidates[0]%%$'\t'*}")
+        return
+    fi
+    # bash can't show descriptions, so they are appended, as long as there is more than one
+    local c
+    for c in "${candidates[@]}"; do
+        if [[ $c == *$'\t'* ]]; then
+            COMPREPLY+=("${c%%$'\t'*}  (${c#*$'\t'})")
+        else
+            COMPREPLY+=("$c")
+        fi
+    done
+}
+complete -o default -F _dt dt
+"#;
+
+const ZSH: &str = r#"_dt() {
+    local -a candidates
+    local line
+    for line in "${(@f)$(dt __complete -- "${(@)words[2,CURRENT]}" 2>/dev/null)}"; do
+        [[ -z $line ]] && continue
+        if [[ $line == *$'\t'* ]]; then
+            candidates+=("${${line%%$'\t'*}//:/\\:}:${line#*$'\t'}")
+        else
+            candidates+=("${line//:/\\:}")
+        fi
+    done
+    if (( ${#candidates} )); then
+        _describe 'dt' candidates
+    else
+        _files
+    fi
+}
+compdef _dt dt
+"#;
+
+const FISH: &str = r#"function __dt_complete
+    set -l candidates (dt __complete -- (commandline -opc)[2..] (commandline -ct) 2>/dev/null)
+    if test (count $candidates) -gt 0
+        printf '%s\n' $candidates
+    else
+        __fish_complete_path (commandline -ct)
+    end
+end
+complete -c dt -f -a '(__dt_complete)'
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use anyhow::Result;
+
+    const CONFIG: &str = r#"
+        menu root {
+            g: git
+            m: misc
+            e: "echo"
+        }
+        menu git {
+            s: "git status"
+            am: "amend" - "git commit --amend"
+            w: cmd {
+                vars branch "Branch to create", dir: path, force: bool
+                "add worktree" - "git worktree add -b $branch $dir"
+            }
+        }
+        menu misc {
+            d: cmd {
+                vars env: choice [dev, prod, preview]
+                "deploy" - "deploy $env"
+            }
+        }
+    "#;
+
+    fn complete(input: &[&str], current: &str) -> Result<Vec<String>> {
+        let config = parse(CONFIG)?;
+        let input: Vec<_> = input.iter().map(|s| s.to_string()).collect();
+        Ok(candidates(&config.menus, &input, current)
+            .into_iter()
+            .map(|c| c.to_string())
+            .collect())
+    }
+
+    #[test]
+    fn keys() -> Result<()> {
+        k9::snapshot!(
+            complete(&[], "")?,
+            r#"
+[
+    "g\tgit",
+    "m\tmisc",
+    "e\t"echo"",
+]
+"#
+        );
+        k9::snapshot!(
+            complete(&[], "g")?,
+            r#"
+[
+    "gs\t"git status"",
+    "gam\tamend",
+    "gw\tadd worktree",
+]
+"#
+        );
+        k9::snapshot!(
+            complete(&[], "ga")?,
+            r#"
+[
+    "gam\tamend",
+]
+"#
+        );
+        k9::snapshot!(
+            complete(&[], "m")?,
+            r#"
+[
+    "md\tdeploy",
+]
+"#
+        );
+        k9::snapshot!(
+            complete(&[], "gw")?,
+            r#"
+[
+    "gw\tadd worktree",
+]
+"#
+        );
+        k9::snapshot!(
+            complete(&[], "x")?,
+            r#"
+[]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn vars() -> Result<()> {
+        k9::snapshot!(
+            complete(&["gw"], "--")?,
+            r#"
+[
+    "--branch\tBranch to create",
+    "--dir",
+    "--force",
+]
+"#
+        );
+        k9::snapshot!(
+            complete(&["gw", "--branch", "main"], "--")?,
+            r#"
+[
+    "--dir",
+    "--force",
+]
+"#
+        );
+        k9::snapshot!(
+            complete(&["gw", "main", "/tmp"], "")?,
+            r#"
+[
+    "true",
+    "false",
+]
+"#
+        );
+        k9::snapshot!(
+            complete(&["gw", "--force"], "t")?,
+            r#"
+[
+    "true",
+]
+"#
+        );
+        k9::snapshot!(
+            complete(&["md"], "p")?,
+            r#"
+[
+    "prod",
+    "preview",
+]
+"#
+        );
+        k9::snapshot!(
+            complete(&["md", "--", "x"], "")?,
+            r#"
+[]
+"#
+        );
+        Ok(())
+    }
+}
@@ -1,18 +1,28 @@
 use anyhow::{anyhow, bail, ensure, Context, Result};
-use console::{pad_str, style, Alignment, Key, Term};
+use console::{measure_text_width, pad_str, style, truncate_str, Alignment, Key, Term};
 use log::debug;
-use rustyline::completion::FilenameCompleter;
+use once_cell::sync::Lazy;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::config::Behavior;
 use rustyline::highlight::Highlighter;
 use rustyline::{Completer, Helper, Hinter, Validator};
 use std::env;
 use std::io::Write;
-use std::path::PathBuf;
-use std::process::Stdio;
+use std::io::{BufRead, BufReader};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
 use std::{fs, io};
 
+use crate::history::History;
 use crate::outproxy::OutProxy;
-use crate::parser::{self, CommandSetting, Menu, Node, SnippetTable};
-use crate::rt_conf;
+use crate::parser::{
+    self, ArgsMode, CommandSetting, Menu, MenuId, Menus, Node, ShellDef, ShellEffect, SnippetTable,
+    VarDef, VarSource, VarType,
+};
+use crate::shell_init::{self, Directive};
+use crate::{inspect, picker, rt_conf};
 
 #[derive(Debug, Clone)]
 enum Submenus<'a> {
@@ -21,17 +31,36 @@ enum Submenus<'a> {
     None,
 }
 
-pub fn run(root_node: &Node, input: &[String], snippet_table: &SnippetTable) -> Result<()> {
-    let mut input_chars = if let Some(input) = input.first() {
-        input.chars().collect()
-    } else {
-        vec![]
-    };
-    let arg_vals = if input.len() > 1 { &input[1..] } else { &[] };
+/// The key that opens the command in an editor before running it, when it isn't used as a key of
+/// an entry. It can be pressed before or after the keys of the command
+const EDIT_KEY: char = '!';
+
+/// The key that opens a search over all commands, when it isn't used as a key of an entry of the
+/// current menu
+const SEARCH_KEY: char = '/';
 
-    let term = Term::stdout();
-    let mut out_proxy = OutProxy::new();
-    let (found_node, input_offset) = follow_path(root_node, &input_chars, 0);
+pub fn run(menus: &Menus, input: &[String], snippet_table: &SnippetTable) -> Result<()> {
+    let root_node = &Node::Menu(menus.root());
+    let mut input_chars = vec![];
+    let mut edit = false;
+    for c in input
+        .first()
+        .map(String::as_str)
+        .unwrap_or_default()
+        .chars()
+    {
+        input_chars.push(c);
+        if take_edit_key(menus, root_node, &mut input_chars) {
+            edit = !edit;
+        }
+    }
+    let mut args = if input.len() > 1 { &input[1..] } else { &[] };
+
+    let term = ui_term();
+    let mut out_proxy = OutProxy::new(term.clone());
+    let cursor_mode = rt_conf::cursor_mode();
+    let mut cursor = Cursor::default();
+    let (found_node, input_offset) = follow_path(menus, root_node, &input_chars, 0);
     let mut input_pos = input_offset;
     let mut current_node = if let Some(found_node) = found_node {
         found_node
@@ -48,29 +77,94 @@ pub fn run(root_node: &Node, input: &[String], snippet_table: &SnippetTable) ->
     loop {
         match current_node {
             Node::Command(c) => {
-                if c.repeat() {
-                    input_chars.pop();
-                } else {
+                let hist_key = history_key(menus, root_node, &input_chars[..input_pos], c);
+                if !c.repeat() {
                     term.clear_last_lines(out_proxy.n_lines)?;
                     term.show_cursor()?;
                 }
-                run_command(c, &term, arg_vals, snippet_table)?;
+                let ran = run_command(
+                    c,
+                    &hist_key,
+                    &term,
+                    &mut out_proxy,
+                    args,
+                    edit,
+                    snippet_table,
+                )?;
+                edit = false;
+                if rt_conf::dry_run() || rt_conf::print_only() {
+                    break Ok(());
+                }
+                // without a terminal, there is no menu to return to
+                if !term.is_term() {
+                    break Ok(());
+                }
+                // the wrapper function can only apply the directives once dt exits
+                if ran && c.shell_effect().is_some() && !c.stay() && !c.repeat() {
+                    break Ok(());
+                }
+                // the menu was cleared before running, unless it's a repeat command
+                if c.stay() || (!ran && !c.repeat()) {
+                    // the arguments were meant for this command only
+                    args = &[];
+                    // keep the output of the command and show the menu it was started from again
+                    out_proxy.reset();
+                    term.hide_cursor()?;
+                    (current_node, input_pos) = pop_to_menu(menus, root_node, &mut input_chars);
+                    continue;
+                }
+                if c.repeat() {
+                    (current_node, input_pos) = repeat_menu(menus, root_node, &mut input_chars);
+                }
             }
-            Node::Menu(m) => {
+            Node::Menu(id) => {
+                ensure_interactive(&term, || {
+                    "so the menu can't be shown. Pass the keys of a command as argument".into()
+                })?;
                 term.clear_last_lines(out_proxy.n_lines)?;
-                out_proxy.n_lines = 0;
-                render_menu(m, &input_chars[input_pos..], &mut out_proxy)?;
+                out_proxy.reset();
+                render_menu(
+                    menus,
+                    *id,
+                    &input_chars[input_pos..],
+                    edit,
+                    cursor_mode.then_some(cursor.selected),
+                    &mut out_proxy,
+                )?;
             }
         }
 
-        // returns true when the user pressed Esc or Ctrl+c, which means we should exit
-        if get_input(&mut input_chars, &term)? {
+        let prev_node = current_node;
+        let action = get_input(&mut input_chars, &term)?;
+        if let Action::Exit = action {
             term.clear_last_lines(out_proxy.n_lines)?;
-            Term::stderr().show_cursor()?;
+            term.show_cursor()?;
             break Ok(());
-        };
+        }
+        if cursor_mode {
+            cursor.act(
+                &action,
+                menus,
+                root_node,
+                current_node,
+                input_pos,
+                &mut input_chars,
+            );
+        }
+        if take_edit_key(menus, root_node, &mut input_chars) {
+            edit = !edit;
+        }
+        if take_search_key(menus, root_node, &mut input_chars) {
+            term.clear_last_lines(out_proxy.n_lines)?;
+            out_proxy.reset();
+            // on Esc, the menu is shown again
+            if let Some(keys) = search(menus, snippet_table, &term) {
+                input_chars = keys.chars().collect();
+            }
+            term.hide_cursor()?;
+        }
 
-        let (found_node, input_offset_) = follow_path(root_node, &input_chars, 0);
+        let (found_node, input_offset_) = follow_path(menus, root_node, &input_chars, 0);
         input_pos = input_offset_;
         current_node = if let Some(found_node) = found_node {
             found_node
@@ -78,15 +172,266 @@ pub fn run(root_node: &Node, input: &[String], snippet_table: &SnippetTable) ->
             input_chars.clear();
             root_node
         };
+        cursor.follow(menus, prev_node, current_node);
     }
 }
 
-type Exit = bool;
-fn get_input(input_chars: &mut Vec<char>, term: &Term) -> Result<Exit> {
+/// The entry of the menu that is highlighted in cursor mode
+#[derive(Default)]
+struct Cursor {
+    /// the index of the entry in [Menu::sorted_entries]
+    selected: usize,
+    /// the menu that was left with [Action::Back], so its entry can be highlighted in the parent
+    left_menu: Option<MenuId>,
+}
+
+impl Cursor {
+    /// Moves the highlight for [Action::Up] and [Action::Down]. [Action::Activate] and
+    /// [Action::Back] change the input instead, to the keys of the highlighted entry or of the
+    /// parent menu. The keys of `current_node` start at `input_pos`
+    fn act(
+        &mut self,
+        action: &Action,
+        menus: &Menus,
+        root_node: &Node,
+        current_node: &Node,
+        input_pos: usize,
+        input_chars: &mut Vec<char>,
+    ) {
+        let Node::Menu(id) = current_node else {
+            return;
+        };
+        let entries = menus[*id].sorted_entries(menus);
+        match action {
+            Action::Up => self.selected = self.selected.saturating_sub(1),
+            Action::Down => {
+                self.selected = (self.selected + 1).min(entries.len().saturating_sub(1));
+            }
+            Action::Activate => {
+                if let Some((keys, _)) = entries.get(self.selected) {
+                    input_chars.truncate(input_pos);
+                    input_chars.extend(keys);
+                }
+            }
+            Action::Back => {
+                input_chars.truncate(input_pos);
+                if !input_chars.is_empty() {
+                    self.left_menu = Some(*id);
+                    pop_to_menu(menus, root_node, input_chars);
+                }
+            }
+            Action::Typed | Action::Exit => {}
+        }
+    }
+
+    /// Highlights the entry of the menu that was left, if `current_node` is its parent, or the
+    /// first entry, if the input led to another node than `prev_node`. When a command of
+    /// `prev_node` is entered, the highlight stays, so it's still there, once the menu is shown
+    /// again
+    fn follow(&mut self, menus: &Menus, prev_node: &Node, current_node: &Node) {
+        let is_entry_of_prev = |node: &Node| match prev_node {
+            Node::Menu(id) => menus[*id]
+                .entries
+                .iter()
+                .any(|(_, n)| std::ptr::eq(n, node)),
+            Node::Command(_) => false,
+        };
+        self.selected = match (self.left_menu.take(), current_node) {
+            (Some(left), Node::Menu(id)) => menus[*id]
+                .sorted_entries(menus)
+                .iter()
+                .position(|(_, node)| matches!(node, Node::Menu(sub) if *sub == left))
+                .unwrap_or_default(),
+            (_, Node::Command(_)) if is_entry_of_prev(current_node) => self.selected,
+            _ if !std::ptr::eq(prev_node, current_node) => 0,
+            _ => self.selected,
+        };
+    }
+}
+
+/// The key the values of the vars of `cmd` are remembered under: the symbol of its menu and its id
+/// or, if it has none, its keys in that menu. Unlike its name or script, it stays the same when
+/// the command is edited, and it's the same no matter how the menu was reached
+fn history_key(menus: &Menus, root_node: &Node, keys: &[char], cmd: &parser::Command) -> String {
+    let mut menu_keys = keys.to_vec();
+    let (menu, pos) = pop_to_menu(menus, root_node, &mut menu_keys);
+    let Node::Menu(id) = menu else {
+        unreachable!("pop_to_menu returns a menu");
+    };
+    match &cmd.id {
+        Some(cmd_id) => format!("{}/{cmd_id}", menus[*id].name),
+        None => format!("{}:{}", menus[*id].name, String::from_iter(&keys[pos..])),
+    }
+}
+
+/// Removes the last key of a repeat command, that was just run, from the input, and returns the
+/// menu it's in and the position its keys start at. The menu is still shown, so typing the key again,
+/// or Enter in cursor mode, repeats the command
+fn repeat_menu<'a>(
+    menus: &'a Menus,
+    root_node: &'a Node,
+    input_chars: &mut Vec<char>,
+) -> (&'a Node, usize) {
+    input_chars.pop();
+    let (found_node, pos) = follow_path(menus, root_node, input_chars, 0);
+    (found_node.unwrap_or(root_node), pos)
+}
+
+/// Removes keys from the end of the input, until it leads to a menu, and returns the menu and the
+/// position its keys start at
+fn pop_to_menu<'a>(
+    menus: &'a Menus,
+    root_node: &'a Node,
+    input_chars: &mut Vec<char>,
+) -> (&'a Node, usize) {
+    loop {
+        input_chars.pop();
+        let (found_node, pos) = follow_path(menus, root_node, input_chars, 0);
+        if let Some(menu @ Node::Menu(_)) = found_node {
+            if pos == input_chars.len() {
+                return (menu, pos);
+            }
+        }
+    }
+}
+
+static UI_TERM: Lazy<Term> = Lazy::new(open_ui_term);
+
+/// The terminal the UI is drawn on and keys are read from. It is the controlling terminal, so
+/// stdout only carries the output of commands. If there is none, nothing is drawn, and
+/// everything that needs the user's input fails
+pub fn ui_term() -> Term {
+    UI_TERM.clone()
+}
+
+#[cfg(unix)]
+fn open_ui_term() -> Term {
+    let open = |path| fs::OpenOptions::new().read(true).write(true).open(path);
+    if let Ok(tty) = open("/dev/tty") {
+        if let Ok(read) = tty.try_clone() {
+            let term = Term::read_write_pair(read, tty);
+            // by default, colors are only enabled, if stdout is a terminal
+            console::set_colors_enabled(term.features().colors_supported());
+            return term;
+        }
+    }
+    match open("/dev/null").and_then(|null| Ok((null.try_clone()?, null))) {
+        Ok((read, write)) => Term::read_write_pair(read, write),
+        Err(_) => Term::stderr(),
+    }
+}
+
+#[cfg(windows)]
+fn open_ui_term() -> Term {
+    if rt_conf::print_only() {
+        Term::stderr()
+    } else {
+        Term::stdout()
+    }
+}
+
+/// Fails with `msg`, if there is no terminal to interact with the user on
+fn ensure_interactive(term: &Term, msg: impl FnOnce() -> String) -> Result<()> {
+    ensure!(term.is_term(), "there is no terminal, {}", msg());
+    Ok(())
+}
+
+fn rl_config() -> rustyline::Config {
+    // read from and draw on the terminal, even if stdin or stdout are redirected
+    rustyline::Config::builder()
+        .behavior(Behavior::PreferTerm)
+        .build()
+}
+
+/// Removes the last key of the input, if it is the [EDIT_KEY] and isn't part of a valid path,
+/// and returns whether it did
+fn take_edit_key(menus: &Menus, root_node: &Node, input_chars: &mut Vec<char>) -> bool {
+    if input_chars.last() != Some(&EDIT_KEY) {
+        return false;
+    }
+    let before = &input_chars[..input_chars.len() - 1];
+    let is_modifier = match follow_path(menus, root_node, before, 0) {
+        // pressed after the keys of a command
+        (Some(Node::Command(_)), _) => true,
+        _ => follow_path(menus, root_node, input_chars, 0).0.is_none(),
+    };
+    if is_modifier {
+        input_chars.pop();
+    }
+    is_modifier
+}
+
+/// Removes the last key of the input, if it is the [SEARCH_KEY] and was pressed in a menu, that
+/// has no entry for it, and returns whether it did
+fn take_search_key(menus: &Menus, root_node: &Node, input_chars: &mut Vec<char>) -> bool {
+    if input_chars.last() != Some(&SEARCH_KEY) {
+        return false;
+    }
+    let before = &input_chars[..input_chars.len() - 1];
+    let in_menu = matches!(
+        follow_path(menus, root_node, before, 0),
+        (Some(Node::Menu(_)), pos) if pos == before.len()
+    );
+    let is_search = in_menu && follow_path(menus, root_node, input_chars, 0).0.is_none();
+    if is_search {
+        input_chars.pop();
+    }
+    is_search
+}
+
+/// Lets the user fuzzy search all commands by their keys, menus, names and scripts, and returns
+/// the keys of the selected one, or None, if the search was aborted
+fn search(menus: &Menus, snippet_table: &SnippetTable, term: &Term) -> Option<String> {
+    let (keys, items): (Vec<_>, Vec<_>) = search_items(menus, snippet_table).into_iter().unzip();
+    let picked = picker::pick_indices(term, "Search", &items, false, None).ok()?;
+    picked.first().map(|i| keys[*i].clone())
+}
+
+/// The keys of every reachable command and the line it is shown as in the search
+fn search_items(menus: &Menus, snippet_table: &SnippetTable) -> Vec<(String, String)> {
+    let reachable = inspect::reachable_commands(menus);
+    let width = reachable
+        .iter()
+        .map(|r| measure_text_width(&r.keys))
+        .max()
+        .unwrap_or_default();
+    reachable
+        .into_iter()
+        .map(|r| {
+            let script = r
+                .cmd
+                .exec_str
+                .resolve(snippet_table)
+                .unwrap_or_else(|_| r.cmd.exec_str.to_string());
+            let script: Vec<_> = script.split_whitespace().collect();
+            let place = match &r.cmd.name {
+                Some(name) => format!("{} > {name}", r.breadcrumb()),
+                None => r.breadcrumb(),
+            };
+            let keys = pad_str(&r.keys, width, Alignment::Left, None);
+            let item = format!("{keys}  {place}  {}", script.join(" "));
+            (r.keys, item)
+        })
+        .collect()
+}
+
+/// What the user did besides typing keys. Only [Action::Exit] is handled outside of cursor mode
+enum Action {
+    Typed,
+    /// Esc or Ctrl+c was pressed
+    Exit,
+    Up,
+    Down,
+    Activate,
+    /// go back to the parent menu
+    Back,
+}
+
+fn get_input(input_chars: &mut Vec<char>, term: &Term) -> Result<Action> {
     let key = match term.read_key() {
         Ok(k) => k,
         Err(e) if e.kind() == io::ErrorKind::Interrupted => {
-            return Ok(true);
+            return Ok(Action::Exit);
         }
         Err(e) => {
             bail!("Error while waiting for key: {e:?}");
@@ -101,79 +446,423 @@ fn get_input(input_chars: &mut Vec<char>, term: &Term) -> Result<Exit> {
         Key::Backspace => {
             input_chars.pop();
         }
-        Key::Escape => {
-            return Ok(true);
-        }
+        Key::Escape => return Ok(Action::Exit),
+        Key::ArrowUp => return Ok(Action::Up),
+        Key::ArrowDown => return Ok(Action::Down),
+        Key::Enter => return Ok(Action::Activate),
+        Key::ArrowLeft => return Ok(Action::Back),
         _ => {}
     }
-    Ok(false)
+    Ok(Action::Typed)
 }
 
+/// Whether the command was actually run, i.e. it wasn't declined when asked for confirmation
+type Ran = bool;
 fn run_command(
     cmd: &parser::Command,
+    hist_key: &str,
     term: &Term,
-    arg_vals: &[String],
+    out_proxy: &mut OutProxy,
+    args: &[String],
+    edit: bool,
     snippet_table: &SnippetTable,
-) -> Result<()> {
-    let mut history = load_hist().context("loading hist")?;
+) -> Result<Ran> {
+    let mut history = History::load(rt_conf::conf_path()).context("loading history")?;
     debug!("Running: {cmd}");
 
-    ensure!(
-        arg_vals.len() <= cmd.env_vars.len(),
-        "Too many arguments for this command"
-    );
+    // everything after -- is passed on to the command as is
+    let (arg_vals, pass_through) = match args.iter().position(|a| a == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (args, &[][..]),
+    };
+    let arg_vals = assign_args(&cmd.env_vars, arg_vals)?;
+    if cmd.shell_effect().is_some() && rt_conf::directive_file().is_none() {
+        ensure!(
+            rt_conf::dry_run() || rt_conf::print_only(),
+            "{cmd} changes the calling shell, which only works when dt is run through the \
+            function of `dt shell-init`"
+        );
+    }
 
     if let Some(wd) = rt_conf::local_conf_dir() {
         env::set_current_dir(wd).context("Changing working directory")?;
     }
 
-    for i in 0..cmd.env_vars.len() {
-        let var = &cmd.env_vars[i];
-        let val = if let Some(val) = arg_vals.get(i) {
-            val
+    let shell = cmd.shell.as_ref().unwrap_or_else(|| rt_conf::shell_def());
+    debug!("shell: {shell:?}");
+
+    let mut n_queried = 0;
+    let mut vals = Vec::with_capacity(cmd.env_vars.len());
+    for (var, arg_val) in cmd.env_vars.iter().zip(arg_vals) {
+        let val = if let Some(val) = arg_val {
+            var.var_type
+                .validate(&val)
+                .context(format!("Invalid value for {}", var.name))?
         } else {
-            history = query_env_var(var, history).context("querying env var")?;
-            history.last().unwrap()
+            let default = match &var.default {
+                Some(default) => default
+                    .resolve(snippet_table)
+                    .context(format!("resolving default value of {}", var.name))?,
+                None => None,
+            };
+            if rt_conf::assume_yes() {
+                let default = default.ok_or(anyhow!("No value and no default for {}", var.name))?;
+                var.var_type
+                    .validate(&default)
+                    .context(format!("Invalid default value for {}", var.name))?
+            } else if let Some(source) = &var.source {
+                ensure_interactive(term, || {
+                    format!("so {} can't be selected. Pass it as argument", var.name)
+                })?;
+                let items = run_generator(source, shell, &vals, snippet_table)
+                    .context(format!("generating values for {}", var.name))?;
+                let picked = picker::pick(
+                    term,
+                    &prompt_for(var),
+                    &items,
+                    source.multi,
+                    default.as_deref(),
+                )?;
+                for p in &picked {
+                    var.var_type
+                        .validate(p)
+                        .context(format!("Invalid value for {}", var.name))?;
+                }
+                picked.join("\n")
+            } else {
+                ensure_interactive(term, || {
+                    format!("so {} can't be queried. Pass it as argument", var.name)
+                })?;
+                let hist = history.values(hist_key, &var.name);
+                let (val, n_lines) = query_env_var(var, default.as_deref(), &hist, term)
+                    .context("querying env var")?;
+                if var.var_type != VarType::Bool {
+                    history.add(hist_key, &var.name, &val);
+                }
+                n_queried += n_lines;
+                val
+            }
         };
-        // uppon calling exec, the env vars are kept, so just setting them here
-        // means setting them for the callee
-        env::set_var(var, val);
+        vals.push((var.name.clone(), val));
     }
-    term.clear_last_lines(cmd.env_vars.len() - arg_vals.len())
+    term.clear_last_lines(n_queried)
         .context("Clearing input lines")?;
-    store_hist(history).context("Storing history")?;
+    history.store().context("Storing history")?;
 
-    let shell = cmd.shell.as_ref().unwrap_or_else(|| rt_conf::shell_def());
-    debug!("shell: {shell:?}");
-    let arg = cmd
+    let script = cmd
         .exec_str
         .resolve(snippet_table)
         .context(format!("resolving {}", cmd.exec_str))?;
-    let args = shell.args_with(arg.as_str());
-    if cmd.settings.contains(&CommandSetting::Repeat) {
-        run_subcommand(
-            &shell.name,
-            &args,
-            cmd.settings.contains(&CommandSetting::IgnoreResult),
-        )
+    let mut script = fill_template(&script, &vals);
+    if edit {
+        ensure_interactive(term, || "so the command can't be edited".into())?;
+        script = edit_script(&script, term).context("editing command")?;
+        if script.trim().is_empty() {
+            return Ok(false);
+        }
+    }
+    let args_mode = cmd.args_mode();
+    let full_script = if args_mode == ArgsMode::Env && !pass_through.is_empty() {
+        // the args have to continue the last line, which a trailing newline would end
+        let script = script.trim_end();
+        ensure!(
+            !ends_in_comment(script),
+            "the script ends in a comment, so the arguments after -- can't be appended to it"
+        );
+        format!("{script} \"$@\"")
+    } else {
+        script.clone()
+    };
+    let mut args = shell.args_with(full_script.as_str());
+    let mut positional = vec![];
+    let envs = match args_mode {
+        ArgsMode::Env => vals.as_slice(),
+        ArgsMode::Positional => {
+            positional.extend(vals.iter().map(|(_, val)| val.as_str()));
+            &[]
+        }
+    };
+    positional.extend(pass_through.iter().map(String::as_str));
+    if !positional.is_empty() {
+        // the first argument after the script becomes $0
+        args.push("dt");
+        args.extend(positional);
+    }
+    if rt_conf::print_only() {
+        println!("{}", printable_command(&shell.name, &args, envs));
+        return Ok(true);
+    }
+    if cmd.confirm() && !rt_conf::assume_yes() && !rt_conf::dry_run() {
+        ensure_interactive(term, || {
+            format!("so {cmd} can't be confirmed. Use --yes to run it anyway")
+        })?;
+        let confirmed = confirm_script(&script, term)?;
+        if !confirmed {
+            return Ok(false);
+        }
+    }
+    if rt_conf::dry_run() {
+        print_dry_run(&shell.name, &args, envs);
+    } else if let Some(effect) = cmd.shell_effect() {
+        run_for_effect(effect, &shell.name, &args, envs)?;
+    } else if cmd.stay() {
+        run_attached(&shell.name, &args, envs, term)?;
+    } else if cmd.settings.contains(&CommandSetting::Repeat) {
+        let ignore_result = cmd.settings.contains(&CommandSetting::IgnoreResult);
+        if let Some(n_lines) = cmd.show_output() {
+            let (status, output) = run_captured(&shell.name, &args, envs)?;
+            render_output_pane(&output, n_lines, term, out_proxy)?;
+            check_status(status, ignore_result)?;
+        } else {
+            run_subcommand(&shell.name, &args, envs, ignore_result)?;
+        }
+    } else {
+        exec_cmd(&shell.name, &args, envs)?;
+    }
+    Ok(true)
+}
+
+/// Builds a command line that runs the program like dt would, when it's entered into an
+/// interactive shell. The vars are set with `env`, so they end up in the environment of the
+/// program and don't change the shell, and the line works in fish as well
+fn printable_command(prog: &str, args: &[&str], envs: &[(String, String)]) -> String {
+    let mut parts = vec![];
+    if !envs.is_empty() {
+        parts.push("env".to_string());
+        parts.extend(
+            envs.iter()
+                .map(|(name, val)| format!("{name}={}", shell_quote(val))),
+        );
+    }
+    parts.push(shell_quote(prog));
+    parts.extend(args.iter().map(|arg| shell_quote(arg)));
+    parts.join(" ")
+}
+
+fn print_dry_run(prog: &str, args: &[&str], envs: &[(String, String)]) {
+    println!("program: {prog:?}");
+    println!("args:");
+    for arg in args {
+        println!("    {arg:?}");
+    }
+    if !envs.is_empty() {
+        println!("env:");
+        for (name, val) in envs {
+            println!("    {name}={val:?}");
+        }
+    }
+}
+
+/// Lets the user edit the script. Scripts with a single line are edited in place, others in
+/// $VISUAL or $EDITOR
+fn edit_script(script: &str, term: &Term) -> Result<String> {
+    if !script.contains('\n') {
+        let mut rl = rustyline::DefaultEditor::with_config(rl_config())?;
+        let prompt = format!("{EDIT_KEY} ");
+        let edited = rl.readline_with_initial(&prompt, (script, ""))?;
+        let width = (term.size().1 as usize).max(1);
+        let n_lines = measure_text_width(&format!("{prompt}{edited}")).div_ceil(width);
+        term.clear_last_lines(n_lines.max(1))?;
+        return Ok(edited);
+    }
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut editor_args = editor.split_whitespace();
+    let editor_prog = editor_args.next().ok_or(anyhow!("$EDITOR is empty"))?;
+    // only we can write to the directory, so nobody can replace the file while it's edited. It
+    // is removed, when it's dropped
+    let dir = tempfile::Builder::new()
+        .prefix("dotree-")
+        .tempdir()
+        .context("creating temporary dir")?;
+    let path = dir.path().join("command.sh");
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(script.as_bytes()))
+        .context("writing temporary file")?;
+    let mut editor_cmd = std::process::Command::new(editor_prog);
+    editor_cmd.args(editor_args).arg(&path);
+    // stdout might be redirected, but the editor needs the terminal
+    #[cfg(unix)]
+    if let Ok(tty) = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+    {
+        editor_cmd.stdin(tty.try_clone()?).stdout(tty);
+    }
+    let status = editor_cmd.status().context(format!("starting {editor}"))?;
+    let edited = fs::read_to_string(&path).context("reading temporary file");
+    ensure!(
+        status.success(),
+        "{editor} didn't exit successfully: {status}"
+    );
+    edited
+}
+
+/// Shows the script that is about to be run and asks whether to run it. The lines that were
+/// printed for that are cleared again afterwards
+fn confirm_script(script: &str, term: &Term) -> Result<bool> {
+    let width = (term.size().1 as usize).max(1);
+    let mut n_lines = 1;
+    for line in script.lines() {
+        term.write_line(&style(line).bold().to_string())?;
+        n_lines += measure_text_width(line).max(1).div_ceil(width);
+    }
+    let prompt = style("Run this command?").red().to_string();
+    let confirmed = match confirm(term, &prompt, Some(false)) {
+        Ok(confirmed) => confirmed,
+        // Esc or Ctrl+c mean no as well
+        Err(_) => {
+            term.write_line("")?;
+            false
+        }
+    };
+    term.clear_last_lines(n_lines)?;
+    Ok(confirmed)
+}
+
+/// Matches the arguments from the command line to the vars. Arguments like `--name value` or
+/// `--name=value` are matched by name, the others by position to the vars that weren't named.
+pub(crate) fn assign_args(vars: &[VarDef], args: &[String]) -> Result<Vec<Option<String>>> {
+    let mut assigned = vec![None; vars.len()];
+    let mut positional = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(name) = arg.strip_prefix("--") else {
+            positional.push(arg.clone());
+            continue;
+        };
+        let (name, val) = match name.split_once('=') {
+            Some((name, val)) => (name, val.to_string()),
+            None => (
+                name,
+                args.next()
+                    .ok_or(anyhow!("Missing value for {arg}"))?
+                    .clone(),
+            ),
+        };
+        let i = vars
+            .iter()
+            .position(|v| v.name == name || v.name == name.replace('-', "_"))
+            .ok_or(anyhow!(
+                "Unknown argument {arg}, this command has no var {name}"
+            ))?;
+        ensure!(assigned[i].is_none(), "{name} was passed more than once");
+        assigned[i] = Some(val);
+    }
+
+    let mut positional = positional.into_iter();
+    for slot in assigned.iter_mut().filter(|slot| slot.is_none()) {
+        *slot = positional.next();
+    }
+    ensure!(
+        positional.next().is_none(),
+        "Too many arguments for this command"
+    );
+    Ok(assigned)
+}
+
+/// Whether the last line of `script` ends in a shell comment, i.e. has a `#` at the start of a
+/// word, outside of quotes
+fn ends_in_comment(script: &str) -> bool {
+    let line = script.lines().last().unwrap_or_default();
+    let mut quote = None;
+    let mut word_start = true;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '#') if word_start => return true,
+            (None | Some('"'), '\\') => {
+                chars.next();
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+        word_start = quote.is_none() && (c.is_whitespace() || ";&|(".contains(c));
+    }
+    false
+}
+
+/// Replaces every `{{name}}` in `script`, where name is one of the vars, with the shell quoted
+/// value of the var. The values are inserted as they are, so placeholders in them stay untouched
+fn fill_template(script: &str, vals: &[(String, String)]) -> String {
+    let mut res = String::new();
+    let mut pos = 0;
+    for (range, name) in parser::placeholders(script) {
+        if let Some((_, val)) = vals.iter().find(|(var, _)| var == name) {
+            res.push_str(&script[pos..range.start]);
+            res.push_str(&shell_quote(val));
+            pos = range.end;
+        }
+    }
+    res.push_str(&script[pos..]);
+    res
+}
+
+fn shell_quote(val: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-.,/:=@%+".contains(c);
+    if !val.is_empty() && val.chars().all(is_safe) {
+        val.to_string()
     } else {
-        exec_cmd(&shell.name, args)
+        shell_init::quote(val)
     }
 }
 
+/// Runs the command of `source` and returns the non empty lines of its output
+fn run_generator(
+    source: &VarSource,
+    shell: &ShellDef,
+    envs: &[(String, String)],
+    snippet_table: &SnippetTable,
+) -> Result<Vec<String>> {
+    let cmd = source
+        .cmd
+        .resolve(snippet_table)
+        .context(format!("resolving {}", source.cmd))?;
+    let output = std::process::Command::new(&shell.name)
+        .args(shell.args_with(&cmd))
+        .envs(envs.iter().cloned())
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()?;
+    ensure!(
+        output.status.success(),
+        "{cmd} didn't exit successfully: {}",
+        output.status
+    );
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim_end)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
 #[cfg(not(windows))]
-fn exec_cmd<'a>(shell_name: &'a str, mut args: Vec<&'a str>) -> Result<()> {
-    args.insert(0, shell_name);
+fn exec_cmd(shell_name: &str, args: &[&str], envs: &[(String, String)]) -> Result<()> {
+    use std::os::unix::process::CommandExt;
     Err(anyhow!(
         "error executing command: \n{:?}",
-        exec::execvp(shell_name, &args)
+        std::process::Command::new(shell_name)
+            .args(args)
+            .envs(envs.iter().cloned())
+            .exec()
     ))
 }
 
 #[cfg(windows)]
-fn exec_cmd(shell_name: &str, args: Vec<&str>) -> Result<()> {
+fn exec_cmd(shell_name: &str, args: &[&str], envs: &[(String, String)]) -> Result<()> {
     // windows doesn't have an exec, let's do this instead
-    let status = std::process::Command::new(shell_name).args(args).status()?;
+    let status = std::process::Command::new(shell_name)
+        .args(args)
+        .envs(envs.iter().cloned())
+        .status()?;
     if !status.success() {
         Err(anyhow!("Process didn't exit successfully: {status:?}"))
     } else {
@@ -181,85 +870,311 @@ fn exec_cmd(shell_name: &str, args: Vec<&str>) -> Result<()> {
     }
 }
 
-fn run_subcommand(prog: &str, args: &[&str], ignore_result: bool) -> Result<()> {
+/// Runs the command with inherited stdio, waits for it and reports its exit status and how long it
+/// took
+fn run_attached(prog: &str, args: &[&str], envs: &[(String, String)], term: &Term) -> Result<()> {
+    let start = Instant::now();
+    let status = std::process::Command::new(prog)
+        .args(args)
+        .envs(envs.iter().cloned())
+        .status()?;
+    let duration = format!("{:.1}s", start.elapsed().as_secs_f64());
+    let report = match status.code() {
+        Some(0) => style(format!("exited successfully after {duration}")).green(),
+        Some(code) => style(format!("exited with code {code} after {duration}")).red(),
+        None => style(format!("was terminated after {duration}")).red(),
+    };
+    term.write_line(&report.to_string())?;
+    Ok(())
+}
+
+fn run_subcommand(
+    prog: &str,
+    args: &[&str],
+    envs: &[(String, String)],
+    ignore_result: bool,
+) -> Result<()> {
     let status = std::process::Command::new(prog)
         .stdout(Stdio::null())
         .stderr(Stdio::null())
         .args(args)
+        .envs(envs.iter().cloned())
         .status()?;
-    if !ignore_result && !status.success() {
-        Err(anyhow!("Process didn't exit successfully: {status:?}"))
-    } else {
-        Ok(())
-    }
+    check_status(status, ignore_result)
 }
 
-fn get_hist_path() -> Result<PathBuf> {
-    let dir = if let Some(sd) = dirs::state_dir() {
-        sd
-    } else {
-        dirs::data_local_dir().ok_or(anyhow!("couldn't get local dir"))?
+/// Runs the command with its stdout captured and turns the output into directives for the
+/// wrapper function. They are applied to dt itself as well, for the commands it runs afterwards
+fn run_for_effect(
+    effect: ShellEffect,
+    prog: &str,
+    args: &[&str],
+    envs: &[(String, String)],
+) -> Result<()> {
+    let output = std::process::Command::new(prog)
+        .args(args)
+        .envs(envs.iter().cloned())
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    check_status(output.status, false)?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let directives = match effect {
+        ShellEffect::Cd => {
+            let dir = stdout
+                .lines()
+                .map(str::trim_end)
+                .rev()
+                .find(|l| !l.is_empty())
+                .ok_or(anyhow!("the command didn't print a directory"))?;
+            let dir = env::current_dir()?
+                .join(dir)
+                .canonicalize()
+                .context(format!("can't change into {dir}"))?;
+            ensure!(dir.is_dir(), "{} is not a directory", dir.display());
+            env::set_current_dir(&dir)?;
+            vec![Directive::Cd(dir)]
+        }
+        ShellEffect::Export => stdout
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|line| {
+                let (name, val) = line
+                    .split_once('=')
+                    .filter(|(name, _)| is_var_name(name))
+                    .ok_or(anyhow!("expected NAME=value, got {line}"))?;
+                env::set_var(name, val);
+                Ok(Directive::Export(name.to_string(), val.to_string()))
+            })
+            .collect::<Result<_>>()?,
     };
-    Ok(dir.join("dthist"))
+    let file = rt_conf::directive_file().expect("checked before running the command");
+    shell_init::emit(file, &directives)
 }
 
-fn load_hist() -> Result<Vec<String>> {
-    let hist_path = get_hist_path()?;
-    Ok(if hist_path.exists() {
-        fs::read_to_string(hist_path)
-            .context("reading file")?
-            .lines()
-            .map(|x| x.to_string())
-            .collect()
-    } else {
-        vec![]
-    })
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
 
-fn store_hist(hist: Vec<String>) -> Result<()> {
-    #[cfg(windows)]
-    let line_ending = "\r\n";
-    #[cfg(not(windows))]
-    let line_ending = "\n";
+/// A line of output of a command and whether it was written to stderr
+type OutputLine = (String, bool);
+
+/// Runs the command and collects the lines it writes to stdout and stderr, in the order they
+/// arrive
+fn run_captured(
+    prog: &str,
+    args: &[&str],
+    envs: &[(String, String)],
+) -> Result<(ExitStatus, Vec<OutputLine>)> {
+    let mut child = std::process::Command::new(prog)
+        .args(args)
+        .envs(envs.iter().cloned())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let lines = Mutex::new(vec![]);
+    let collect = |out: &mut dyn BufRead, is_err: bool| {
+        for line in out.lines().map_while(Result::ok) {
+            lines.lock().unwrap().push((line, is_err));
+        }
+    };
+    thread::scope(|s| {
+        s.spawn(|| collect(&mut BufReader::new(stdout), false));
+        s.spawn(|| collect(&mut BufReader::new(stderr), true));
+    });
+    let status = child.wait()?;
+    Ok((status, lines.into_inner().unwrap()))
+}
 
-    fs::write(get_hist_path()?, hist.join(line_ending))?;
+/// Replaces the output pane below the menu with the last `n_lines` lines of `output`
+fn render_output_pane(
+    output: &[OutputLine],
+    n_lines: usize,
+    term: &Term,
+    out_proxy: &mut OutProxy,
+) -> Result<()> {
+    term.clear_last_lines(out_proxy.pane_lines)?;
+    out_proxy.n_lines -= out_proxy.pane_lines;
+    let lines_before = out_proxy.n_lines;
+    let width = term.size().1 as usize;
+    for (line, is_err) in &output[output.len().saturating_sub(n_lines)..] {
+        // lines must not wrap, otherwise the pane can't be cleared correctly
+        let line = truncate_str(line, width.saturating_sub(2), "…");
+        if *is_err {
+            writeln!(out_proxy, "{} {}", style("│").dim(), style(line).red())?;
+        } else {
+            writeln!(out_proxy, "{} {line}", style("│").dim())?;
+        }
+    }
+    out_proxy.pane_lines = out_proxy.n_lines - lines_before;
     Ok(())
 }
 
+fn check_status(status: ExitStatus, ignore_result: bool) -> Result<()> {
+    if !ignore_result && !status.success() {
+        Err(anyhow!("Process didn't exit successfully: {status:?}"))
+    } else {
+        Ok(())
+    }
+}
+
 #[derive(Helper, Completer, Hinter, Validator)]
 struct RlHelper {
     #[rustyline(Completer)]
-    completer: FilenameCompleter,
+    completer: VarCompleter,
 }
 impl Highlighter for RlHelper {}
 
-fn query_env_var(name: &str, mut hist: Vec<String>) -> Result<Vec<String>> {
-    let mut rl = rustyline::Editor::new()?;
+enum VarCompleter {
+    Filename(FilenameCompleter),
+    Choices(Vec<String>),
+    None,
+}
+
+impl VarCompleter {
+    fn for_type(var_type: &VarType) -> Self {
+        match var_type {
+            VarType::String | VarType::Path(_) => VarCompleter::Filename(FilenameCompleter::new()),
+            VarType::Choice(choices) => VarCompleter::Choices(choices.clone()),
+            VarType::Int { .. } | VarType::Bool => VarCompleter::None,
+        }
+    }
+}
+
+impl Completer for VarCompleter {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        match self {
+            VarCompleter::Filename(completer) => completer.complete(line, pos, ctx),
+            VarCompleter::Choices(choices) => Ok((
+                0,
+                choices
+                    .iter()
+                    .filter(|c| c.starts_with(&line[..pos]))
+                    .map(|c| Pair {
+                        display: c.clone(),
+                        replacement: c.clone(),
+                    })
+                    .collect(),
+            )),
+            VarCompleter::None => Ok((0, vec![])),
+        }
+    }
+}
+
+/// Asks for the value of `var` until a valid one is entered. Returns the value and the number of
+/// lines that were printed while asking for it
+fn query_env_var(
+    var: &VarDef,
+    default: Option<&str>,
+    hist: &[&str],
+    term: &Term,
+) -> Result<(String, usize)> {
+    let prompt = prompt_for(var);
+    if var.var_type == VarType::Bool {
+        let default = default.map(|d| var.var_type.validate(d)).transpose()?;
+        let val = confirm(term, &prompt, default.map(|d| d == "true"))?;
+        return Ok((val.to_string(), 1));
+    }
+
+    let prompt = match &var.var_type {
+        VarType::Choice(choices) => format!("{prompt} [{}]: ", choices.join("/")),
+        _ => format!("{prompt}: "),
+    };
+    let mut rl = rustyline::Editor::with_config(rl_config())?;
     rl.set_helper(Some(RlHelper {
-        completer: FilenameCompleter::new(),
+        completer: VarCompleter::for_type(&var.var_type),
     }));
-    for h in &hist {
-        rl.add_history_entry(h)?;
+    for h in hist {
+        rl.add_history_entry(*h)?;
+    }
+    let mut n_lines = 0;
+    let mut initial = default.unwrap_or_default().to_string();
+    loop {
+        let line = rl.readline_with_initial(&prompt, (&initial, ""))?;
+        n_lines += 1;
+        match var.var_type.validate(&line) {
+            Ok(val) => break Ok((val, n_lines)),
+            Err(e) => {
+                term.write_line(&style(format!("{e}")).red().to_string())?;
+                n_lines += 1;
+                initial = line;
+            }
+        }
+    }
+}
+
+fn prompt_for(var: &VarDef) -> String {
+    if let Some(prompt) = &var.prompt {
+        prompt.clone()
+    } else {
+        format!("Value for {}", var.name)
     }
-    let line = rl.readline(&format!("Value for {name}: "))?;
-    hist.push(line);
-    Ok(hist)
+}
+
+/// Asks a yes/no question, that is answered with a single key press. Enter selects the default,
+/// if there is one
+fn confirm(term: &Term, prompt: &str, default: Option<bool>) -> Result<bool> {
+    let options = match default {
+        Some(true) => "[Y/n]",
+        Some(false) => "[y/N]",
+        None => "[y/n]",
+    };
+    term.write_str(&format!("{prompt} {options} "))?;
+    let answer = loop {
+        match term.read_key()? {
+            Key::Char('y' | 'Y') => break true,
+            Key::Char('n' | 'N') => break false,
+            Key::Enter if default.is_some() => break default.unwrap(),
+            Key::Escape => bail!("aborted"),
+            _ => {}
+        }
+    };
+    term.write_line(if answer { "y" } else { "n" })?;
+    Ok(answer)
 }
 
 fn render_menu(
-    current_menu: &Menu,
+    menus: &Menus,
+    current_menu: MenuId,
     remaining_path: &[char],
+    edit: bool,
+    selected: Option<usize>,
     out_proxy: &mut OutProxy,
 ) -> Result<()> {
+    let current_menu = &menus[current_menu];
+    if edit {
+        writeln!(
+            out_proxy,
+            "{}",
+            style(format!(
+                "{EDIT_KEY} the command will be edited before it runs"
+            ))
+            .yellow()
+        )?;
+    }
     let remaining_path = String::from_iter(remaining_path);
     let keysection_len = current_menu
         .entries
-        .keys()
-        .map(|keys| keys.len())
+        .iter()
+        .map(|(keys, _)| keys.len())
         .max()
         .expect("empty menu")
         + 1;
-    for (keys, node) in &current_menu.entries {
+    for (i, (keys, node)) in current_menu.sorted_entries(menus).into_iter().enumerate() {
         let keys = String::from_iter(keys);
         let keys = if let Some(rest) = keys.strip_prefix(&remaining_path) {
             format!(
@@ -271,15 +1186,36 @@ fn render_menu(
             format!("{keys}:")
         };
         let keys = pad_str(&keys, keysection_len, Alignment::Left, None);
-        writeln!(out_proxy, "{keys} {node}")?;
+        let line = match node {
+            Node::Command(c) if c.confirm() => {
+                format!("{keys} {}", style(node.display(menus)).red())
+            }
+            _ => format!("{keys} {}", node.display(menus)),
+        };
+        match selected {
+            Some(selected) if selected == i => {
+                writeln!(out_proxy, "{} {line}", style(">").cyan().bold())?
+            }
+            Some(_) => writeln!(out_proxy, "  {line}")?,
+            None => writeln!(out_proxy, "{line}")?,
+        }
     }
     Ok(())
 }
 
-fn follow_path<'a>(node: &'a Node, input_chars: &[char], pos: usize) -> (Option<&'a Node>, usize) {
+/// Follows the keys in `input_chars` from `node` and returns the node they lead to, with the
+/// position up to which they were used, or None, if they don't match any entry
+pub fn follow_path<'a>(
+    menus: &'a Menus,
+    node: &'a Node,
+    input_chars: &[char],
+    pos: usize,
+) -> (Option<&'a Node>, usize) {
     match node {
-        Node::Menu(this) => match find_submenus_for(this, input_chars, pos) {
-            Submenus::Exact(next_node, new_pos) => follow_path(next_node, input_chars, new_pos),
+        Node::Menu(this) => match find_submenus_for(&menus[*this], input_chars, pos) {
+            Submenus::Exact(next_node, new_pos) => {
+                follow_path(menus, next_node, input_chars, new_pos)
+            }
             Submenus::Incomplete(new_pos) => (Some(node), new_pos),
             Submenus::None => (None, 0),
         },
@@ -287,6 +1223,20 @@ fn follow_path<'a>(node: &'a Node, input_chars: &[char], pos: usize) -> (Option<
     }
 }
 
+/// Finds the keys that lead to a command by its name, which is the symbol of its menu and its id,
+/// separated by a `/` or a `.`, like `git/amend_all`
+pub fn follow_name(menus: &Menus, name: &str) -> Result<String> {
+    let (menu, id) = name
+        .split_once(['/', '.'])
+        .ok_or(anyhow!("expected a name like menu/command, got {name}"))?;
+    ensure!(menus.find(menu).is_some(), "there is no menu {menu}");
+    inspect::reachable_commands(menus)
+        .into_iter()
+        .find(|r| r.path.last().is_some_and(|m| m.name == menu) && r.cmd.id.as_deref() == Some(id))
+        .map(|r| r.keys)
+        .ok_or(anyhow!("menu {menu} has no command with the id {id}"))
+}
+
 fn find_submenus_for<'a>(menu: &'a Menu, input_chars: &[char], pos: usize) -> Submenus<'a> {
     // The base idea here is to compare the path with valid entries character wise.
     // A vec of options of chars is used, so it can be set to none, if it doesn't match any more
@@ -323,3 +1273,359 @@ fn find_submenus_for<'a>(menu: &'a Menu, input_chars: &[char], pos: usize) -> Su
         Submenus::Incomplete(pos)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_args() {
+        let vars: Vec<_> = ["branch", "output_dir", "force"]
+            .iter()
+            .map(|name| VarDef {
+                name: name.to_string(),
+                var_type: VarType::String,
+                source: None,
+                default: None,
+                prompt: None,
+            })
+            .collect();
+        let assign = |args: &[&str]| {
+            let args: Vec<_> = args.iter().map(|a| a.to_string()).collect();
+            assign_args(&vars, &args).map_err(|e| e.to_string())
+        };
+        k9::snapshot!(
+            assign(&["--output-dir", "/tmp", "main"]),
+            r#"
+Ok(
+    [
+        Some(
+            "main",
+        ),
+        Some(
+            "/tmp",
+        ),
+        None,
+    ],
+)
+"#
+        );
+        k9::snapshot!(
+            assign(&["a", "--branch=main", "b"]),
+            r#"
+Ok(
+    [
+        Some(
+            "main",
+        ),
+        Some(
+            "a",
+        ),
+        Some(
+            "b",
+        ),
+    ],
+)
+"#
+        );
+        k9::snapshot!(
+            assign(&["--name", "x"]),
+            r#"
+Err(
+    "Unknown argument --name, this command has no var name",
+)
+"#
+        );
+        k9::snapshot!(
+            assign(&["a", "b", "c", "d"]),
+            r#"
+Err(
+    "Too many arguments for this command",
+)
+"#
+        );
+        k9::snapshot!(
+            assign(&["a", "--branch"]),
+            r#"
+Err(
+    "Missing value for --branch",
+)
+"#
+        );
+    }
+
+    #[test]
+    fn edit_key() -> Result<()> {
+        let config = parser::parse(
+            r#"
+            menu root {
+                g: git
+            }
+            menu git {
+                a: "echo a"
+                !: "echo bang"
+            }
+            "#,
+        )?;
+        let root_node = &Node::Menu(config.menus.root());
+        let is_modifier = |input: &str| {
+            let mut input_chars: Vec<_> = input.chars().collect();
+            take_edit_key(&config.menus, root_node, &mut input_chars)
+        };
+        k9::snapshot!(
+            ["!", "g!", "ga!", "gx!"].map(is_modifier),
+            r#"
+[
+    true,
+    false,
+    true,
+    true,
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn searching() -> Result<()> {
+        let config = parser::parse(
+            r#"
+            menu root {
+                g: git
+                /: "echo slash"
+            }
+            menu git {
+                s: "git status"
+                am: "amend" - "git commit --amend " + $all
+            }
+            snippet all = "--all"
+            "#,
+        )?;
+        let root_node = &Node::Menu(config.menus.root());
+        let is_search = |input: &str| {
+            let mut input_chars: Vec<_> = input.chars().collect();
+            take_search_key(&config.menus, root_node, &mut input_chars)
+        };
+        k9::snapshot!(
+            ["/", "g/", "gs/", "ga/"].map(is_search),
+            r#"
+[
+    false,
+    true,
+    false,
+    false,
+]
+"#
+        );
+        k9::snapshot!(
+            search_items(&config.menus, &config.snippet_table),
+            r#"
+[
+    (
+        "gs",
+        "gs   root > git  git status",
+    ),
+    (
+        "gam",
+        "gam  root > git > amend  git commit --amend --all",
+    ),
+    (
+        "/",
+        "/    root  echo slash",
+    ),
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cursor() -> Result<()> {
+        let config = parser::parse(
+            r#"
+            menu root {
+                s: "echo s"
+                g: git
+            }
+            menu git {
+                s: "git status"
+                r: cmd {
+                    set repeat
+                    "echo repeat"
+                }
+                a: "git add"
+            }
+            "#,
+        )?;
+        let menus = &config.menus;
+        let root_node = &Node::Menu(menus.root());
+        let mut cursor = Cursor::default();
+        let mut input_chars = vec![];
+        let mut current_node = root_node;
+        let mut input_pos = 0;
+        let mut step = |action: Action| {
+            // what run does after a repeat command ran
+            if let Node::Command(cmd) = current_node {
+                assert!(cmd.repeat());
+                (current_node, input_pos) = repeat_menu(menus, root_node, &mut input_chars);
+            }
+            cursor.act(
+                &action,
+                menus,
+                root_node,
+                current_node,
+                input_pos,
+                &mut input_chars,
+            );
+            let prev_node = current_node;
+            let found_node;
+            (found_node, input_pos) = follow_path(menus, root_node, &input_chars, 0);
+            current_node = found_node.unwrap();
+            cursor.follow(menus, prev_node, current_node);
+            (String::from_iter(&input_chars), cursor.selected)
+        };
+        k9::snapshot!(
+            [
+                Action::Up,
+                Action::Down,
+                Action::Down,
+                Action::Activate,
+                Action::Down,
+                Action::Back,
+                Action::Activate,
+                Action::Down,
+                Action::Activate,
+                Action::Activate,
+                Action::Down,
+                Action::Down,
+                Action::Activate,
+            ]
+            .map(&mut step),
+            r#"
+[
+    (
+        "",
+        0,
+    ),
+    (
+        "",
+        1,
+    ),
+    (
+        "",
+        1,
+    ),
+    (
+        "g",
+        0,
+    ),
+    (
+        "g",
+        1,
+    ),
+    (
+        "",
+        1,
+    ),
+    (
+        "g",
+        0,
+    ),
+    (
+        "g",
+        1,
+    ),
+    (
+        "gr",
+        1,
+    ),
+    (
+        "gr",
+        1,
+    ),
+    (
+        "g",
+        2,
+    ),
+    (
+        "g",
+        2,
+    ),
+    (
+        "ga",
+        2,
+    ),
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn history_keys() -> Result<()> {
+        let config = parser::parse(
+            r#"
+            menu root {
+                g: git
+                o: other
+            }
+            menu git {
+                am: "amend" - "git commit --amend"
+                w: cmd worktree { "git worktree add" }
+            }
+            menu other {
+                g: git
+            }
+            "#,
+        )?;
+        let root_node = &Node::Menu(config.menus.root());
+        let key = |keys: &str| {
+            let chars: Vec<_> = keys.chars().collect();
+            let Some(Node::Command(cmd)) = follow_path(&config.menus, root_node, &chars, 0).0
+            else {
+                panic!("expected a command");
+            };
+            history_key(&config.menus, root_node, &chars, cmd)
+        };
+        k9::snapshot!(
+            ["gam", "ogam", "gw"].map(key),
+            r#"
+[
+    "git:am",
+    "git:am",
+    "git/worktree",
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn template() {
+        let vals = vec![
+            ("name".to_string(), "it's me".to_string()),
+            ("dir".to_string(), "/tmp/dir".to_string()),
+            ("empty".to_string(), String::new()),
+        ];
+        k9::snapshot!(
+            fill_template(
+                "echo {{name}} {{dir}} {{empty}} {{.Other}} {{unknown}} {{{dir}}} {name}",
+                &vals
+            ),
+            r#"
+"echo 'it'\\''s me' /tmp/dir '' {{.Other}} {{unknown}} {/tmp/dir} {name}"
+"#
+        );
+        // values are never expanded again, so they can't inject unquoted text
+        let vals = vec![
+            ("a".to_string(), "{{b}}".to_string()),
+            ("b".to_string(), "x; rm -rf ~".to_string()),
+        ];
+        k9::snapshot!(
+            fill_template("echo {{a}} {{b}}", &vals),
+            r#"
+"echo '{{b}}' 'x; rm -rf ~'"
+"#
+        );
+    }
+}
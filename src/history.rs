@@ -0,0 +1,254 @@
+use anyhow::{anyhow, Context, Result};
+use fd_lock::RwLock;
+use hashbrown::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// how many values are kept for every var
+const MAX_VALUES_PER_VAR: usize = 100;
+
+/// The values entered for the vars of commands, kept separately for every config, command and var.
+///
+/// It is stored as tab separated lines of timestamp, config, command, var and value, oldest first.
+/// Storing merges the values added in this session with the ones stored by other sessions in the
+/// meantime, while holding a lock, so concurrent sessions don't lose entries.
+pub struct History {
+    path: PathBuf,
+    config: String,
+    entries: Vec<Entry>,
+    /// entries that were added in this session and aren't stored yet
+    added: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    timestamp: u64,
+    config: String,
+    command: String,
+    var: String,
+    value: String,
+}
+
+impl History {
+    /// Loads the history of the config at `config` from the users state directory
+    pub fn load(config: &Path) -> Result<Self> {
+        Self::load_from(get_hist_path()?, config)
+    }
+
+    fn load_from(path: PathBuf, config: &Path) -> Result<Self> {
+        let config = config
+            .canonicalize()
+            .unwrap_or_else(|_| config.to_owned())
+            .to_string_lossy()
+            .into_owned();
+        let entries = read_entries(&path)?
+            .into_iter()
+            .filter(|e| e.config == config)
+            .collect();
+        Ok(History {
+            path,
+            config,
+            entries,
+            added: vec![],
+        })
+    }
+
+    /// The previous values of `var` of `command`, the most recent last
+    pub fn values(&self, command: &str, var: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .chain(&self.added)
+            .filter(|e| e.command == command && e.var == var)
+            .map(|e| e.value.as_str())
+            .collect()
+    }
+
+    pub fn add(&mut self, command: &str, var: &str, value: &str) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.added.push(Entry {
+            timestamp,
+            config: self.config.clone(),
+            command: command.to_string(),
+            var: var.to_string(),
+            value: value.to_string(),
+        });
+    }
+
+    pub fn store(&self) -> Result<()> {
+        if self.added.is_empty() {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).context("creating state dir")?;
+        }
+        let mut lock_path = self.path.clone().into_os_string();
+        lock_path.push(".lock");
+        let mut lock = RwLock::new(File::create(lock_path).context("creating lock file")?);
+        let _guard = lock.write().context("locking history")?;
+
+        // other sessions might have stored entries since we loaded it
+        let mut entries = read_entries(&self.path)?;
+        entries.extend(self.added.iter().cloned());
+        let entries = dedup_and_cap(entries);
+
+        let content: String = entries
+            .iter()
+            .map(|e| format!("{}\n", e.to_line()))
+            .collect();
+        // write to a temporary file first, so readers never see a partially written history
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, content).context("writing history")?;
+        fs::rename(&tmp_path, &self.path).context("replacing history")?;
+        Ok(())
+    }
+}
+
+/// Removes all but the latest occurrence of every value and keeps only the latest
+/// [MAX_VALUES_PER_VAR] values of every var
+fn dedup_and_cap(entries: Vec<Entry>) -> Vec<Entry> {
+    let mut seen = HashSet::new();
+    let mut counts = HashMap::new();
+    let mut kept: Vec<_> = entries
+        .into_iter()
+        .rev()
+        .filter(|e| {
+            let key = (e.config.clone(), e.command.clone(), e.var.clone());
+            let count = counts.entry(key.clone()).or_insert(0);
+            if *count >= MAX_VALUES_PER_VAR || !seen.insert((key, e.value.clone())) {
+                return false;
+            }
+            *count += 1;
+            true
+        })
+        .collect();
+    kept.reverse();
+    kept
+}
+
+fn read_entries(path: &Path) -> Result<Vec<Entry>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let content = fs::read_to_string(path).context("reading history")?;
+    // lines that can't be parsed, e.g. from an older format, are skipped
+    Ok(content.lines().filter_map(Entry::from_line).collect())
+}
+
+fn get_hist_path() -> Result<PathBuf> {
+    let dir = if let Some(sd) = dirs::state_dir() {
+        sd
+    } else {
+        dirs::data_local_dir().ok_or(anyhow!("couldn't get local dir"))?
+    };
+    Ok(dir.join("dotree").join("history.tsv"))
+}
+
+impl Entry {
+    fn to_line(&self) -> String {
+        [
+            &self.timestamp.to_string(),
+            &self.config,
+            &self.command,
+            &self.var,
+            &self.value,
+        ]
+        .map(|field| escape(field))
+        .join("\t")
+    }
+
+    fn from_line(line: &str) -> Option<Entry> {
+        let fields: Vec<_> = line.split('\t').map(unescape).collect();
+        let [timestamp, config, command, var, value] = <[String; 5]>::try_from(fields).ok()?;
+        Some(Entry {
+            timestamp: timestamp.parse().ok()?,
+            config,
+            command,
+            var,
+            value,
+        })
+    }
+}
+
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(field: &str) -> String {
+    let mut res = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => res.push('\t'),
+            Some('n') => res.push('\n'),
+            Some('r') => res.push('\r'),
+            Some(other) => res.push(other),
+            None => res.push('\\'),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_sessions() -> Result<()> {
+        let dir = tempfile::Builder::new()
+            .prefix("dotree_history_")
+            .tempdir()?;
+        let path = dir.path().join("history.tsv");
+        let config = Path::new("/some/dotree.dt");
+
+        let mut first = History::load_from(path.clone(), config)?;
+        let mut second = History::load_from(path.clone(), config)?;
+        first.add("gw", "branch", "main");
+        first.add("gw", "branch", "two\tlines\nwith \\ escapes");
+        second.add("gw", "branch", "dev");
+        second.add("gw", "branch", "main");
+        second.add("gw", "dir", "/tmp");
+        first.store()?;
+        second.store()?;
+
+        let loaded = History::load_from(path.clone(), config)?;
+        k9::snapshot!(
+            loaded.values("gw", "branch"),
+            r#"
+[
+    "two\tlines\nwith \\ escapes",
+    "dev",
+    "main",
+]
+"#
+        );
+        k9::snapshot!(
+            loaded.values("gw", "dir"),
+            r#"
+[
+    "/tmp",
+]
+"#
+        );
+        let other_config = History::load_from(path, Path::new("/other/dotree.dt"))?;
+        k9::snapshot!(
+            other_config.values("gw", "branch"),
+            r#"
+[]
+"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Context, Result};
+use console::{measure_text_width, pad_str, Alignment};
+use hashbrown::HashSet;
+
+use crate::core;
+use crate::parser::{Command, Menu, MenuId, Menus, Node, ShellDef, SnippetTable};
+
+/// A command together with the way to reach it from the root menu
+pub struct Reachable<'a> {
+    pub keys: String,
+    /// the menus on the way to the command, starting with the root menu
+    pub path: Vec<&'a Menu>,
+    pub cmd: &'a Command,
+}
+
+impl Reachable<'_> {
+    pub fn breadcrumb(&self) -> String {
+        let names: Vec<_> = self.path.iter().map(|m| m.to_string()).collect();
+        names.join(" > ")
+    }
+}
+
+/// All commands that can be reached from the root menu, in the order they are displayed in.
+/// Every menu is visited only once, since menus can link back to their parents
+pub fn reachable_commands(menus: &Menus) -> Vec<Reachable<'_>> {
+    let mut res = vec![];
+    let mut visited = HashSet::new();
+    collect_commands(
+        menus,
+        menus.root(),
+        &mut vec![],
+        &mut String::new(),
+        &mut visited,
+        &mut res,
+    );
+    res
+}
+
+fn collect_commands<'a>(
+    menus: &'a Menus,
+    id: MenuId,
+    path: &mut Vec<&'a Menu>,
+    keys: &mut String,
+    visited: &mut HashSet<MenuId>,
+    res: &mut Vec<Reachable<'a>>,
+) {
+    visited.insert(id);
+    let menu = &menus[id];
+    path.push(menu);
+    for (entry_keys, node) in menu.sorted_entries(menus) {
+        let len = keys.len();
+        keys.extend(entry_keys);
+        match node {
+            Node::Command(cmd) => res.push(Reachable {
+                keys: keys.clone(),
+                path: path.clone(),
+                cmd,
+            }),
+            Node::Menu(sub) if !visited.contains(sub) => {
+                collect_commands(menus, *sub, path, keys, visited, res)
+            }
+            Node::Menu(_) => {}
+        }
+        keys.truncate(len);
+    }
+    path.pop();
+}
+
+/// Finds the command at the end of `keys`, following links between menus the way
+/// [core::follow_path] does when they are typed
+pub fn find_command<'a>(menus: &'a Menus, keys: &str) -> Option<Reachable<'a>> {
+    let chars: Vec<_> = keys.chars().collect();
+    let root_node = Node::Menu(menus.root());
+    let (Some(Node::Command(_)), end) = core::follow_path(menus, &root_node, &chars, 0) else {
+        return None;
+    };
+    if end != chars.len() {
+        return None;
+    }
+    // every prefix of the keys that ends exactly at a menu is a step on the way to the command
+    let mut path = vec![&menus[menus.root()]];
+    let mut menu_end = 0;
+    for len in 1..chars.len() {
+        if let (Some(Node::Menu(id)), pos) = core::follow_path(menus, &root_node, &chars[..len], 0)
+        {
+            if pos == len {
+                path.push(&menus[*id]);
+                menu_end = len;
+            }
+        }
+    }
+    let (_, node) = path
+        .last()?
+        .entries
+        .iter()
+        .find(|(entry_keys, _)| *entry_keys == chars[menu_end..])?;
+    let Node::Command(cmd) = node else {
+        return None;
+    };
+    Some(Reachable {
+        keys: keys.to_string(),
+        path,
+        cmd,
+    })
+}
+
+/// Prints a table of all reachable commands with their keys, menus, names and shells
+pub fn print_list(menus: &Menus, default_shell: &ShellDef) {
+    let mut rows = vec![["KEYS", "MENU", "NAME", "SHELL"].map(String::from)];
+    rows.extend(reachable_commands(menus).iter().map(|r| {
+        [
+            r.keys.clone(),
+            r.breadcrumb(),
+            r.cmd.to_string(),
+            r.cmd.shell.as_ref().unwrap_or(default_shell).to_string(),
+        ]
+    }));
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(measure_text_width(cell));
+        }
+    }
+    for row in &rows {
+        let cells: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| pad_str(cell, width, Alignment::Left, None))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+/// Prints everything about the command at `keys`: where it's defined, its settings and vars,
+/// the shell it runs in and its script, with the snippets resolved
+pub fn print_which(
+    menus: &Menus,
+    keys: &str,
+    snippet_table: &SnippetTable,
+    default_shell: &ShellDef,
+) -> Result<()> {
+    let found = find_command(menus, keys).ok_or(anyhow!("{keys} doesn't lead to a command"))?;
+    let cmd = found.cmd;
+    let script = cmd
+        .exec_str
+        .resolve(snippet_table)
+        .context(format!("resolving {}", cmd.exec_str))?;
+
+    println!("name:     {cmd}");
+    if let (Some(id), Some(menu)) = (&cmd.id, found.path.last()) {
+        println!("id:       {}/{id}", menu.name);
+    }
+    println!("keys:     {}", found.keys);
+    println!("menu:     {}", found.breadcrumb());
+    println!("location: {}", cmd.location);
+    if !cmd.settings.is_empty() {
+        let settings: Vec<_> = cmd.settings.iter().map(|s| s.to_string()).collect();
+        println!("settings: {}", settings.join(", "));
+    }
+    for (i, var) in cmd.env_vars.iter().enumerate() {
+        let label = if i == 0 { "vars:" } else { "" };
+        println!("{label:<10}{var}");
+    }
+    println!("shell:    {}", cmd.shell.as_ref().unwrap_or(default_shell));
+    println!("script:");
+    for line in script.lines() {
+        println!("    {line}");
+    }
+    Ok(())
+}
@@ -1,4 +1,9 @@
+pub mod completion;
 pub mod core;
+pub mod history;
+pub mod inspect;
 pub mod outproxy;
 pub mod parser;
+pub mod picker;
 pub mod rt_conf;
+pub mod shell_init;
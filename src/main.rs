@@ -1,35 +1,43 @@
-use std::{env, fs, path::PathBuf, process::exit};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::exit,
+};
 
 use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use console::Term;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use dotree::{
-    core::run,
-    parser::{self, Config, Node, ShellDef},
-    rt_conf,
+    completion,
+    core::{self, run, ui_term},
+    inspect,
+    parser::{self, Config, Menus, Node, ShellDef},
+    rt_conf::{self, RtConf},
+    shell_init::{self, InitShell},
 };
 
 fn main() -> Result<()> {
     pretty_env_logger::init();
-    let args = Args::parse();
+    let args = Args::parse_from(hoist_flags(env::args().collect()));
+    if let Some(Commands::ShellInit { shell }) = args.command {
+        print!("{}", shell_init::script(shell));
+        return Ok(());
+    }
 
-    let (conf_path, local_conf_dir) = if args.local_mode {
-        if let Some(path) = search_local_config().context("Searching local config")? {
-            let conf_dir = path.parent().unwrap().to_owned();
-            (path, Some(conf_dir))
-        } else {
-            eprintln!("Couldnt find a local config");
+    if let Some(Commands::Completions { shell }) = args.command {
+        print!("{}", completion::script(shell));
+        return Ok(());
+    }
+    if let Some(Commands::Complete { words }) = args.command {
+        complete(words);
+        return Ok(());
+    }
+
+    let (conf_path, local_conf_dir) = match locate_config(args.local_mode, args.conf_file) {
+        Ok(location) => location,
+        Err(e) => {
+            eprintln!("{e:#}");
             exit(1);
         }
-    } else if let Some(p) = args.conf_file {
-        (p, None)
-    } else {
-        (
-            get_default_config_dir()
-                .ok_or(anyhow!("Couldn't determin config dir"))?
-                .join("dotree.dt"),
-            None,
-        )
     };
 
     if !conf_path.exists() {
@@ -40,28 +48,233 @@ fn main() -> Result<()> {
         exit(1);
     }
 
-    let conf_src = fs::read_to_string(conf_path).context("loading config")?;
+    if let Some(Commands::Check) = args.command {
+        return check(&conf_path);
+    }
+
     let Config {
-        menu,
+        menus,
         shell_def: file_shell_def,
         snippet_table,
-    } = parser::parse(&conf_src).context("Parsing Config")?;
+    } = match parse_config(&conf_path, args.menu.as_deref()) {
+        Ok(conf) => conf,
+        Err(e) => {
+            eprintln!("{e:#}");
+            exit(1);
+        }
+    };
 
     let env_shell = get_shell_from_env()
         .context("Getting Shell from Env")?
         .unwrap_or_default();
     let shell = file_shell_def.unwrap_or(env_shell);
-    rt_conf::init(local_conf_dir, shell);
+    match &args.command {
+        Some(Commands::List) => {
+            inspect::print_list(&menus, &shell);
+            return Ok(());
+        }
+        Some(Commands::Which { keys }) => {
+            return inspect::print_which(&menus, keys, &snippet_table, &shell);
+        }
+        _ => {}
+    }
+    // only this invocation is meant to write to it, not the ones in the commands it runs
+    let directive_file = env::var_os(shell_init::DIRECTIVE_FILE_VAR).map(PathBuf::from);
+    env::remove_var(shell_init::DIRECTIVE_FILE_VAR);
+    rt_conf::init(RtConf {
+        conf_path,
+        local_conf_dir,
+        shell,
+        assume_yes: args.yes,
+        dry_run: args.dry_run,
+        print_only: args.print,
+        directive_file,
+        cursor_mode: args.cursor,
+    });
 
-    let term = Term::stdout();
+    let term = ui_term();
     term.hide_cursor()?;
-    let res = run(&Node::Menu(menu), &args.input, &snippet_table);
+    let input = match args.command {
+        Some(Commands::Run { name, args }) => {
+            let keys = core::follow_name(&menus, &name)?;
+            std::iter::once(keys).chain(args).collect()
+        }
+        _ => args.input,
+    };
+    let res = run(&menus, &input, &snippet_table);
     if let Err(e) = term.show_cursor() {
         eprintln!("Warning, couldn't show cursor again:\n{e:?}");
     }
     res
 }
 
+/// Moves the flags of dt, that were given after the keys, like `dt gw -y`, in front of them, since
+/// everything after the keys is taken as values for the vars of the command. Arguments after `--`
+/// are left as they are, and so are the words to complete
+fn hoist_flags(args: Vec<String>) -> Vec<String> {
+    let cmd = Args::command();
+    // the flag `arg` is, and whether its value is the next argument
+    let find_flag = |arg: &str| {
+        let (name, has_value) = match arg.split_once('=') {
+            Some((name, _)) => (name, true),
+            None => (arg, false),
+        };
+        cmd.get_arguments()
+            .find(|a| {
+                a.get_long().is_some_and(|l| name == format!("--{l}"))
+                    || a.get_short().is_some_and(|s| name == format!("-{s}"))
+            })
+            .map(|a| (a.get_action().takes_values(), has_value))
+            .filter(|(takes_value, has_value)| *takes_value || !has_value)
+    };
+    let mut flags = vec![];
+    let mut rest = vec![];
+    let mut after_keys = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" || (!after_keys && arg == "__complete") {
+            rest.push(arg);
+            rest.extend(args.by_ref());
+        } else if let Some((takes_value, has_value)) = find_flag(&arg) {
+            flags.push(arg);
+            if takes_value && !has_value {
+                flags.extend(args.next());
+            }
+        } else if flags.is_empty() {
+            // the name of the binary
+            flags.push(arg);
+        } else {
+            after_keys |= !arg.starts_with('-');
+            rest.push(arg);
+        }
+    }
+    flags.extend(rest);
+    flags
+}
+
+/// Returns the path of the config file and, in local mode, the directory it's in
+fn locate_config(
+    local_mode: bool,
+    conf_file: Option<PathBuf>,
+) -> Result<(PathBuf, Option<PathBuf>)> {
+    Ok(if local_mode {
+        let path = search_local_config()
+            .context("Searching local config")?
+            .ok_or(anyhow!("Couldnt find a local config"))?;
+        let conf_dir = path.parent().unwrap().to_owned();
+        (path, Some(conf_dir))
+    } else if let Some(p) = conf_file {
+        (p, None)
+    } else {
+        (
+            get_default_config_dir()
+                .ok_or(anyhow!("Couldn't determin config dir"))?
+                .join("dotree.dt"),
+            None,
+        )
+    })
+}
+
+/// Prints the candidates for the last of `words`, which are the words of the command line after
+/// dt. Nothing is printed, if the config can't be loaded
+fn complete(mut words: Vec<String>) {
+    let current = words.pop().unwrap_or_default();
+    let shells_expected = matches!(
+        words.last().map(String::as_str),
+        Some("shell-init" | "completions")
+    );
+    if shells_expected {
+        for shell in InitShell::value_variants() {
+            if let Some(name) = shell.to_possible_value() {
+                if name.get_name().starts_with(&current) {
+                    println!("{}", name.get_name());
+                }
+            }
+        }
+        return;
+    }
+    let words = std::iter::once("dt".to_string()).chain(words).collect();
+    let Ok(args) = Args::try_parse_from(hoist_flags(words)) else {
+        return;
+    };
+    if args.command.is_some() {
+        return;
+    }
+    let Ok((conf_path, _)) = locate_config(args.local_mode, args.conf_file) else {
+        return;
+    };
+    let Ok(config) = parse_config(&conf_path, args.menu.as_deref()) else {
+        return;
+    };
+    for candidate in completion::candidates(&config.menus, &args.input, &current) {
+        println!("{candidate}");
+    }
+}
+
+/// Parses the config, starting at `menu` instead of the root menu, if it is given
+fn parse_config(conf_path: &Path, menu: Option<&str>) -> Result<Config> {
+    match menu {
+        Some(menu) => parser::parse_file_with_root(conf_path, menu),
+        None => parser::parse_file(conf_path),
+    }
+}
+
+fn check(conf_path: &Path) -> Result<()> {
+    let findings = match parser::check_file(conf_path) {
+        Ok(findings) => findings,
+        Err(e) => {
+            eprintln!("{e:#}");
+            exit(1);
+        }
+    };
+    // the config was already parsed successfully while checking it
+    let collisions = parser::parse_file(conf_path)
+        .map(|config| subcommand_collisions(&config.menus))
+        .unwrap_or_default();
+    // notes don't count as problems
+    for note in &findings.notes {
+        println!("{note}\n");
+    }
+    let problems = findings.warnings;
+    if problems.is_empty() && collisions.is_empty() {
+        println!("No problems found in {}", conf_path.display());
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{problem}\n");
+    }
+    for collision in &collisions {
+        println!("{collision}\n");
+    }
+    println!(
+        "Found {} problem(s) in {}",
+        problems.len() + collisions.len(),
+        conf_path.display()
+    );
+    exit(1);
+}
+
+/// Finds the keys of the root menu, that spell the name of a subcommand, so `dt <keys>` runs the
+/// subcommand instead of the entry
+fn subcommand_collisions(menus: &Menus) -> Vec<String> {
+    let root_node = Node::Menu(menus.root());
+    Args::command()
+        .get_subcommands()
+        .filter_map(|sub| {
+            let name = sub.get_name();
+            let chars: Vec<_> = name.chars().collect();
+            match core::follow_path(menus, &root_node, &chars, 0) {
+                (Some(node), pos) if pos == chars.len() => Some(format!(
+                    "the keys {name} lead to {}, but `dt {name}` runs the {name} subcommand. \
+                    Use `dt -- {name}` to enter them",
+                    node.display(menus)
+                )),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
 fn get_default_config_dir() -> Option<PathBuf> {
     if let Ok(path) = env::var("XDG_CONFIG_HOME") {
         Some(path.into())
@@ -96,16 +309,78 @@ fn search_local_config() -> Result<Option<PathBuf>> {
 
 #[derive(Parser)]
 struct Args {
-    /// Input that will be process character by character, as if it was entered
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Input that will be process character by character, as if it was entered, followed by
+    /// the values of the command's vars, either by position or by name, e.g. `--branch main`.
+    /// Arguments after `--` are passed on to the command as "$@"
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     input: Vec<String>,
 
     /// path to config file. Defaults to $XDG_CONFIG_HOME/dotree.dt
-    #[arg(long, short)]
+    #[arg(long, short, global = true)]
     conf_file: Option<PathBuf>,
 
     /// instead of reading the config file, search all directories from current
     /// to root for a dotree.dt file, and use this, if it is found.
     /// All commands are executed from the files directory
-    #[arg(long, short)]
+    #[arg(long, short, global = true)]
     local_mode: bool,
+
+    /// start in the menu with this symbol instead of the root menu. Keys are relative to it
+    #[arg(long, short, global = true)]
+    menu: Option<String>,
+
+    /// don't ask for the values of variables, that weren't passed as arguments, but use their
+    /// default values instead
+    #[arg(long, short)]
+    yes: bool,
+
+    /// don't execute the command, but print the program, arguments and environment variables it
+    /// would be executed with
+    #[arg(long)]
+    dry_run: bool,
+
+    /// don't execute the command, but print it to stdout, e.g. to put it into the shell's command
+    /// line
+    #[arg(long)]
+    print: bool,
+
+    /// highlight an entry of the menu, that is moved with the arrow keys and run or opened with
+    /// Enter. Left goes back to the parent menu
+    #[arg(long)]
+    cursor: bool,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Check the config for problems, like unreachable entries or unused snippets, without
+    /// running anything. Exits with a non-zero code if any were found
+    Check,
+    /// List all commands with their keys, menus, names and shells
+    List,
+    /// Show where the command at the given keys is defined, its settings, vars and shell, and
+    /// its script with the snippets resolved
+    Which { keys: String },
+    /// Run a command by its name, which is the symbol of its menu and the id of the command, like
+    /// `git/amend_all`. It is followed by the values of the command's vars, like the keys
+    Run {
+        name: String,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Print a script for the given shell, that wraps dt, so `set cd` and `set export` commands
+    /// can change the shell, and binds Alt+o to open dt and insert the selected command into the
+    /// command line. Use it like `eval "$(dt shell-init bash)"`
+    ShellInit { shell: InitShell },
+    /// Print a script for the given shell, that completes the keys of entries and the vars of
+    /// commands. Use it like `eval "$(dt completions bash)"`
+    Completions { shell: InitShell },
+    /// Print the candidates for completing the last of the given words
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
 }
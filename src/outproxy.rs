@@ -1,29 +1,39 @@
-use std::io::{stdout, Write};
+use console::Term;
+use std::io::Write;
 
 pub struct OutProxy {
+    term: Term,
     pub n_lines: usize,
+    /// how many of the lines belong to the output pane below the menu
+    pub pane_lines: usize,
 }
 
 impl Write for OutProxy {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         self.n_lines += count_newlines(buf);
-        stdout().write(buf)
+        self.term.write(buf)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
-        stdout().flush()
+        self.term.flush()
     }
 }
 
 impl OutProxy {
-    pub fn new() -> Self {
-        OutProxy { n_lines: 0 }
+    pub fn new(term: Term) -> Self {
+        OutProxy {
+            term,
+            n_lines: 0,
+            pane_lines: 0,
+        }
     }
 }
 
-impl Default for OutProxy {
-    fn default() -> Self {
-        Self::new()
+impl OutProxy {
+    /// forgets about all lines, e.g. because they were cleared
+    pub fn reset(&mut self) {
+        self.n_lines = 0;
+        self.pane_lines = 0;
     }
 }
 
@@ -1,13 +1,18 @@
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use std::collections::VecDeque;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 use pest::{
+    error::{Error, ErrorVariant, InputLocation},
     iterators::{Pair, Pairs},
-    Parser,
+    Parser, Span,
 };
 use pest_derive::Parser;
+use typed_arena::Arena;
 
-use anyhow::{anyhow, ensure, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 
 #[derive(Parser)]
 #[grammar = "grammar.pest"]
@@ -15,30 +20,139 @@ struct ConfigParser;
 
 #[derive(Debug, Clone)]
 pub enum Node {
-    Menu(Menu),
+    Menu(MenuId),
     Command(Command),
 }
 
+/// Refers to a menu in [Menus]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct MenuId(usize);
+
+/// All menus that are reachable from the root menu. Each menu is parsed only once, and entries
+/// refer to their submenus by id, so menus can be shared and can refer back to their parents.
+#[derive(Debug, Clone)]
+pub struct Menus(Vec<Menu>);
+
 #[derive(Debug, Clone)]
 pub struct Menu {
     pub name: String,
     pub display_name: Option<String>,
-    pub entries: HashMap<Vec<char>, Node>,
+    /// the entries in the order they were declared in
+    pub entries: Vec<(Vec<char>, Node)>,
+    pub settings: Vec<MenuSetting>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MenuSetting {
+    SortByKey,
+    SortByName,
 }
 
 #[derive(Debug, Clone)]
 pub struct Command {
+    /// identifies the command within its menu, so it can be run as `menu/id`
+    pub id: Option<String>,
     pub exec_str: StringExpr,
     pub settings: Vec<CommandSetting>,
     pub name: Option<String>,
     pub shell: Option<ShellDef>,
-    pub env_vars: Vec<String>,
+    pub env_vars: Vec<VarDef>,
+    /// where the entry of the command is defined
+    pub location: Location,
+}
+
+/// A position in a config file
+#[derive(Debug, Clone, Default)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    pub col: usize,
+}
+
+/// A variable of a command, which is queried before the command is run
+#[derive(Debug, Clone)]
+pub struct VarDef {
+    pub name: String,
+    pub var_type: VarType,
+    /// a command, whose output lines are offered for selection
+    pub source: Option<VarSource>,
+    pub default: Option<VarDefault>,
+    /// shown instead of "Value for <name>" when querying the value
+    pub prompt: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VarSource {
+    pub cmd: StringExpr,
+    /// whether several lines can be selected, which are then joined by newlines
+    pub multi: bool,
+}
+
+/// Determines which values are valid for a variable
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum VarType {
+    #[default]
+    String,
+    Path(Option<PathCheck>),
+    /// an integer within the inclusive bounds
+    Int {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    /// a yes/no question, the value is either "true" or "false"
+    Bool,
+    Choice(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathCheck {
+    Existing,
+    New,
 }
 
+#[derive(Debug, Clone)]
+pub enum VarDefault {
+    Expr(StringExpr),
+    /// the value of an environment variable, if it is set
+    Env(String),
+}
+
+/// how many lines of output are shown by default with `set show_output`
+const DEFAULT_OUTPUT_LINES: usize = 5;
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum CommandSetting {
     Repeat,
     IgnoreResult,
+    /// run the command attached to the terminal and return to the menu afterwards
+    Stay,
+    /// ask for confirmation before running the command
+    Confirm,
+    /// show the last n lines of the output of a repeat command below the menu
+    ShowOutput(usize),
+    Args(ArgsMode),
+    /// apply the output of the command to the calling shell
+    Effect(ShellEffect),
+}
+
+/// How the output of a command changes the shell dt was called from, through the wrapper
+/// function of `dt shell-init`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ShellEffect {
+    /// change into the directory on the last line of the output
+    Cd,
+    /// export every `NAME=value` line of the output
+    Export,
+}
+
+/// How the values of the vars are passed to the command
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ArgsMode {
+    /// as environment variables
+    #[default]
+    Env,
+    /// as positional arguments, i.e. $1..$n
+    Positional,
 }
 
 #[derive(Debug, Clone)]
@@ -51,6 +165,22 @@ pub struct ShellDef {
 struct RawMenu<'a> {
     display_name: Option<String>,
     body: Pairs<'a, Rule>,
+    file: &'a Path,
+    name: Span<'a>,
+}
+
+#[derive(Debug, Clone)]
+struct SnippetDef<'a> {
+    file: &'a Path,
+    name: Span<'a>,
+}
+
+#[derive(Debug, Clone)]
+struct SourceFile {
+    path: PathBuf,
+    /// canonical path of the file, used to recognize a file that is included multiple times
+    id: PathBuf,
+    src: String,
 }
 
 #[derive(Debug, Clone)]
@@ -61,7 +191,7 @@ pub enum StringExprElem {
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub menu: Menu,
+    pub menus: Menus,
     pub shell_def: Option<ShellDef>,
     pub snippet_table: SnippetTable,
 }
@@ -69,6 +199,10 @@ pub struct Config {
 #[derive(Debug, Clone)]
 pub struct StringExpr(Vec<StringExprElem>);
 
+/// All errors found while parsing a config, each pointing to its location in the source
+#[derive(Debug)]
+pub struct ParseErrors(pub Vec<Error<Rule>>);
+
 pub type SnippetTable = HashMap<String, StringExpr>;
 
 trait INext: Sized {
@@ -87,44 +221,228 @@ impl INext for Pair<'_, Rule> {
     }
 }
 
+impl SourceFile {
+    fn new(path: PathBuf, src: String) -> Self {
+        let id = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        SourceFile { path, id, src }
+    }
+}
+
 fn from_string(p: Pair<'_, Rule>) -> String {
     p.nnext(2).as_str().to_string()
 }
 
 pub fn parse(src: &str) -> Result<Config> {
-    let mut pairs = ConfigParser::parse(Rule::file, src).context("Parsing source")?;
-    let file = pairs.next().unwrap();
-    assert!(file.as_rule() == Rule::file);
-    let mut entries = file.into_inner();
-    let mut shell_def = None;
+    let (config, _) = parse_sources(
+        SourceFile::new(PathBuf::new(), src.to_string()),
+        ROOT,
+        false,
+    )?;
+    Ok(config)
+}
+
+/// Parses the config file at `path`, including all files it references via `include`
+pub fn parse_file(path: &Path) -> Result<Config> {
+    parse_file_with_root(path, ROOT)
+}
+
+/// Parses the config file at `path` like [parse_file], but with the menu `root` in place of the
+/// root menu, so only the menus reachable from it are parsed, and keys are relative to it
+pub fn parse_file_with_root(path: &Path, root: &str) -> Result<Config> {
+    let src = fs::read_to_string(path).context(format!("Reading {}", path.display()))?;
+    let (config, _) = parse_sources(SourceFile::new(path.to_owned(), src), root, false)?;
+    Ok(config)
+}
+
+/// What [check_file] found in a config, that doesn't prevent it from being used
+pub struct Findings {
+    /// problems like unreachable entries or unused snippets
+    pub warnings: Vec<Error<Rule>>,
+    /// things that may be intended, like menus that can only be opened with `--menu`
+    pub notes: Vec<Error<Rule>>,
+}
+
+/// Parses the config file at `path` like [parse_file], but returns the problems that don't
+/// prevent the config from being used, like unreachable entries or unused snippets. Menus that
+/// can't be reached from the root menu are parsed as well, as they can be opened with `--menu`
+pub fn check_file(path: &Path) -> Result<Findings> {
+    let src = fs::read_to_string(path).context(format!("Reading {}", path.display()))?;
+    let (_, findings) = parse_sources(SourceFile::new(path.to_owned(), src), ROOT, true)?;
+    Ok(findings)
+}
+
+/// the menu that is shown first, unless a different one is chosen
+const ROOT: &str = "root";
+
+/// Parses `root` and the files it includes, starting at the menu `root_menu`. With `all_menus`,
+/// the menus that can't be reached from it are parsed too
+fn parse_sources(root: SourceFile, root_menu: &str, all_menus: bool) -> Result<(Config, Findings)> {
+    let mut errors = vec![];
+    // the parsed files borrow the sources, so they have to stay in place while more are loaded
+    let arena = Arena::new();
+    let mut sources = vec![];
+    let mut files = vec![];
+    load_sources(
+        &arena,
+        root,
+        &mut vec![],
+        &mut sources,
+        &mut files,
+        &mut errors,
+    );
+    if !errors.is_empty() {
+        return Err(ParseErrors(errors).into());
+    }
 
-    if let Some(first_entry) = entries.peek() {
+    let mut shell_def = None;
+    if let Some(first_entry) = files[0].1.peek() {
         if first_entry.as_rule() == Rule::shell_def {
             shell_def = Some(parse_shell_def(first_entry));
-            _ = entries.next();
         }
     }
 
-    let menus = get_menu_table(entries.clone());
-    let snippet_table = get_snippet_table(entries);
-    let menu = parse_menu("root", &menus)?;
+    let mut builder = ConfigBuilder {
+        menus: get_menu_table(&files, &mut errors),
+        snippets: get_snippet_defs(&files, &mut errors),
+        errors,
+        warnings: vec![],
+        notes: vec![],
+        menu_ids: vec![],
+        reachable_menus: 0,
+        used_snippets: HashSet::new(),
+    };
+    let snippet_table = builder.get_snippet_table(&files);
+    let menus = if builder.menus.contains_key(root_menu) {
+        Some(builder.parse_menus(root_menu, all_menus))
+    } else if root_menu != ROOT {
+        bail!("there is no menu {root_menu}");
+    } else {
+        let root = sources[0];
+        builder.errors.push(error_at(
+            Span::new(&root.src, 0, 0).unwrap(),
+            &root.path,
+            "missing root menu",
+        ));
+        None
+    };
+
+    match menus {
+        Some(menus) if builder.errors.is_empty() => {
+            builder.lint(&snippet_table);
+            let config = Config {
+                menus,
+                shell_def,
+                snippet_table,
+            };
+            let findings = Findings {
+                warnings: sorted(builder.warnings),
+                notes: sorted(builder.notes),
+            };
+            Ok((config, findings))
+        }
+        _ => Err(ParseErrors(sorted(builder.errors)).into()),
+    }
+}
+
+/// Loads `file` and, recursively, all files included by it into `sources`, and their parsed
+/// entries into `files`. `stack` contains the ids of the files that are currently being loaded
+/// and is used to detect cycles.
+fn load_sources<'a>(
+    arena: &'a Arena<SourceFile>,
+    file: SourceFile,
+    stack: &mut Vec<PathBuf>,
+    sources: &mut Vec<&'a SourceFile>,
+    files: &mut Vec<(&'a Path, Pairs<'a, Rule>)>,
+    errors: &mut Vec<Error<Rule>>,
+) {
+    let file: &SourceFile = arena.alloc(file);
+    let mut includes = vec![];
+    match ConfigParser::parse(Rule::file, &file.src) {
+        Ok(mut pairs) => {
+            let entries = pairs.next().unwrap().into_inner();
+            for entry in entries.clone() {
+                match entry.as_rule() {
+                    Rule::shell_def if !stack.is_empty() => errors.push(error_at(
+                        entry.as_span(),
+                        &file.path,
+                        "a shell directive is only allowed in the main config file",
+                    )),
+                    Rule::include => {
+                        let span = entry.as_span();
+                        includes.push((from_string(entry.inext()), span));
+                    }
+                    _ => {}
+                }
+            }
+            files.push((file.path.as_path(), entries));
+        }
+        Err(e) => errors.push(with_path(e, &file.path)),
+    }
 
-    Ok(Config {
-        menu,
-        shell_def,
-        snippet_table,
-    })
+    let base_dir = file.path.parent().unwrap_or(Path::new(""));
+    stack.push(file.id.clone());
+    sources.push(file);
+    for (include, span) in includes {
+        let include_error = |message: String| error_at(span, &file.path, message);
+        let path = base_dir.join(include);
+        let src = match fs::read_to_string(&path) {
+            Ok(src) => src,
+            Err(e) => {
+                errors.push(include_error(format!(
+                    "couldn't read {}: {e}",
+                    path.display()
+                )));
+                continue;
+            }
+        };
+        let included = SourceFile::new(path, src);
+        if let Some(pos) = stack.iter().position(|id| *id == included.id) {
+            let chain: Vec<_> = stack[pos..]
+                .iter()
+                .map(|id| sources.iter().find(|f| f.id == *id).copied().unwrap())
+                .chain(std::iter::once(&included))
+                .map(|f| f.path.display().to_string())
+                .collect();
+            errors.push(include_error(format!(
+                "include cycle: {}",
+                chain.join(" -> ")
+            )));
+        } else if !sources.iter().any(|f| f.id == included.id) {
+            // a file that is included from multiple places only needs to be loaded once
+            load_sources(arena, included, stack, sources, files, errors);
+        }
+    }
+    stack.pop();
 }
 
-fn get_snippet_table(entries: Pairs<'_, Rule>) -> HashMap<String, StringExpr> {
-    let mut res = HashMap::new();
-    for e in entries {
-        if e.as_rule() == Rule::snippet {
-            let mut e = e.into_inner();
-            res.insert(
-                e.next().unwrap().as_str().to_string(),
-                parse_string_expr(e.next().unwrap()),
-            );
+/// Collects the names of all snippets, together with the location they are defined at
+fn get_snippet_defs<'a>(
+    files: &[(&'a Path, Pairs<'a, Rule>)],
+    errors: &mut Vec<Error<Rule>>,
+) -> HashMap<&'a str, SnippetDef<'a>> {
+    let mut res: HashMap<&str, SnippetDef<'_>> = HashMap::new();
+    for (path, entries) in files {
+        for e in entries.clone().filter(|e| e.as_rule() == Rule::snippet) {
+            let name = e.inext();
+            if let Some(first) = res.get(name.as_str()) {
+                errors.push(error_at(
+                    name.as_span(),
+                    path,
+                    format!(
+                        "snippet {} is already defined in {}",
+                        name.as_str(),
+                        first.file.display()
+                    ),
+                ));
+            } else {
+                res.insert(
+                    name.as_str(),
+                    SnippetDef {
+                        file: path,
+                        name: name.as_span(),
+                    },
+                );
+            }
         }
     }
     res
@@ -151,11 +469,13 @@ fn parse_shell_def(p: Pair<'_, Rule>) -> ShellDef {
     }
 }
 
-fn get_menu_table(pairs: Pairs<'_, Rule>) -> HashMap<&str, RawMenu<'_>> {
-    pairs
-        .into_iter()
-        .filter(|x| x.as_rule() == Rule::menu)
-        .map(|menu| {
+fn get_menu_table<'a>(
+    files: &[(&'a Path, Pairs<'a, Rule>)],
+    errors: &mut Vec<Error<Rule>>,
+) -> HashMap<&'a str, RawMenu<'a>> {
+    let mut res: HashMap<&str, RawMenu<'_>> = HashMap::new();
+    for (path, entries) in files {
+        for menu in entries.clone().filter(|x| x.as_rule() == Rule::menu) {
             let mut menu_elems = menu.into_inner();
             let first_child = menu_elems.next().unwrap();
             let (display_name, menu_name) = if first_child.as_rule() == Rule::string {
@@ -163,87 +483,622 @@ fn get_menu_table(pairs: Pairs<'_, Rule>) -> HashMap<&str, RawMenu<'_>> {
             } else {
                 (None, first_child)
             };
-            (
+            if let Some(first) = res.get(menu_name.as_str()) {
+                errors.push(error_at(
+                    menu_name.as_span(),
+                    path,
+                    format!(
+                        "menu {} is already defined in {}",
+                        menu_name.as_str(),
+                        first.file.display()
+                    ),
+                ));
+                continue;
+            }
+            res.insert(
                 menu_name.as_str(),
                 RawMenu {
                     display_name,
                     body: menu_elems.next().unwrap().into_inner(),
+                    file: path,
+                    name: menu_name.as_span(),
                 },
-            )
-        })
-        .collect()
-}
-
-fn parse_menu(name: &str, menus: &HashMap<&str, RawMenu<'_>>) -> Result<Menu> {
-    let mut entries = HashMap::new();
-    let RawMenu { display_name, body } = menus
-        .get(name)
-        .ok_or(anyhow!("Undefined symbol: {name}"))?
-        .clone();
-    for entry in body {
-        let mut children = entry.into_inner();
-        let keys = children.next().unwrap().as_str().chars().collect();
-        let child_pair = children.next().unwrap();
-        let next_node = match child_pair.as_rule() {
-            Rule::symbol => {
-                let submenu_name = child_pair.as_str();
-                Node::Menu(
-                    parse_menu(submenu_name, menus)
-                        .context(format!("Parsing submenu: {submenu_name}"))?,
-                )
+            );
+        }
+    }
+    res
+}
+
+/// Turns the raw menus and snippets into their final representation. Errors are collected
+/// instead of returned, so that as many of them as possible can be reported at once.
+struct ConfigBuilder<'a> {
+    menus: HashMap<&'a str, RawMenu<'a>>,
+    snippets: HashMap<&'a str, SnippetDef<'a>>,
+    errors: Vec<Error<Rule>>,
+    /// problems that don't prevent the config from being used, reported by `dt check`
+    warnings: Vec<Error<Rule>>,
+    /// findings reported by `dt check`, that don't count as problems
+    notes: Vec<Error<Rule>>,
+    /// the names of all menus that are referenced, directly or indirectly, from the root menu,
+    /// followed by the other ones, if they are parsed too. The position of a name is the id of the
+    /// menu
+    menu_ids: Vec<String>,
+    /// the number of menus in `menu_ids` that are reachable from the root menu. Only `dt check`
+    /// parses the others as well
+    reachable_menus: usize,
+    used_snippets: HashSet<String>,
+}
+
+impl<'a> ConfigBuilder<'a> {
+    fn get_snippet_table(&mut self, files: &[(&'a Path, Pairs<'a, Rule>)]) -> SnippetTable {
+        let mut res = HashMap::new();
+        for (path, entries) in files {
+            for e in entries.clone().filter(|e| e.as_rule() == Rule::snippet) {
+                let mut e = e.into_inner();
+                let name = e.next().unwrap().as_str();
+                // only the first definition counts, the others were reported as duplicates
+                if self.snippets[name].file == *path && !res.contains_key(name) {
+                    res.insert(
+                        name.to_string(),
+                        self.parse_string_expr(e.next().unwrap(), path),
+                    );
+                }
             }
-            Rule::quick_command => {
-                let (display_name, exec_str) = parse_quick_command(child_pair);
-                Node::Command(Command {
-                    exec_str,
-                    name: display_name,
-                    settings: vec![],
-                    env_vars: vec![],
-                    shell: None,
-                })
+        }
+        res
+    }
+
+    /// Parses the root menu and all menus that are reachable from it. With `all`, the other menus
+    /// are parsed after them, so their errors are found as well
+    fn parse_menus(&mut self, root: &str, all: bool) -> Menus {
+        let mut res = vec![];
+        self.menu_id(root);
+        self.parse_new_menus(&mut res);
+        self.reachable_menus = res.len();
+        if all {
+            let mut unreachable: Vec<_> = self
+                .menus
+                .iter()
+                .filter(|(name, _)| !self.menu_ids.iter().any(|n| n == *name))
+                .map(|(name, menu)| (menu.file, menu.name.start(), name.to_string()))
+                .collect();
+            unreachable.sort();
+            for (_, _, name) in unreachable {
+                self.menu_id(&name);
+            }
+            self.parse_new_menus(&mut res);
+        }
+        Menus(res)
+    }
+
+    /// Parses the menus in `menu_ids` that aren't in `res` yet
+    fn parse_new_menus(&mut self, res: &mut Vec<Menu>) {
+        // parsing a menu can add new ids, which are parsed in turn
+        while res.len() < self.menu_ids.len() {
+            let name = self.menu_ids[res.len()].clone();
+            res.push(self.parse_menu(&name));
+        }
+    }
+
+    /// Returns the id of the menu with the given name. Menus that weren't referenced before get a
+    /// new id and are parsed by [Self::parse_menus] later on.
+    fn menu_id(&mut self, name: &str) -> MenuId {
+        if let Some(pos) = self.menu_ids.iter().position(|n| n == name) {
+            MenuId(pos)
+        } else {
+            self.menu_ids.push(name.to_string());
+            MenuId(self.menu_ids.len() - 1)
+        }
+    }
+
+    fn parse_menu(&mut self, name: &str) -> Menu {
+        let mut entries = vec![];
+        let mut settings = vec![];
+        let RawMenu {
+            display_name,
+            body,
+            file,
+            ..
+        } = self.menus[name].clone();
+        let mut keydefs: Vec<Pair<'_, Rule>> = vec![];
+        let mut command_ids = vec![];
+        for entry in body {
+            if entry.as_rule() == Rule::menu_settings {
+                settings = self.parse_menu_settings(entry, file);
+                continue;
+            }
+            let mut children = entry.into_inner();
+            let keydef = children.next().unwrap();
+            self.check_keydef(&keydef, &keydefs, file);
+            let (line, col) = keydef.as_span().start_pos().line_col();
+            let location = Location {
+                file: file.to_owned(),
+                line,
+                col,
+            };
+            let keys = keydef.as_str().chars().collect();
+            keydefs.push(keydef);
+            let child_pair = children.next().unwrap();
+            let next_node = match child_pair.as_rule() {
+                Rule::symbol => {
+                    let submenu_name = child_pair.as_str();
+                    if !self.menus.contains_key(submenu_name) {
+                        self.errors.push(error_at(
+                            child_pair.as_span(),
+                            file,
+                            format!("undefined menu: {submenu_name}"),
+                        ));
+                        continue;
+                    }
+                    Node::Menu(self.menu_id(submenu_name))
+                }
+                Rule::quick_command => {
+                    let (display_name, exec_str) = self.parse_quick_command(child_pair, file);
+                    Node::Command(Command {
+                        id: None,
+                        exec_str,
+                        name: display_name,
+                        settings: vec![],
+                        env_vars: vec![],
+                        shell: None,
+                        location,
+                    })
+                }
+                Rule::anon_command => {
+                    let id = child_pair
+                        .clone()
+                        .into_inner()
+                        .find(|p| p.as_rule() == Rule::command_id);
+                    if let Some(id) = id {
+                        if command_ids.contains(&id.as_str()) {
+                            self.errors.push(error_at(
+                                id.as_span(),
+                                file,
+                                format!("command id {} is already used in this menu", id.as_str()),
+                            ));
+                        }
+                        command_ids.push(id.as_str());
+                    }
+                    Node::Command(Command {
+                        location,
+                        ..self.parse_anon_command(child_pair, file)
+                    })
+                }
+                _ => {
+                    panic!("unexpected rule: {child_pair:?}")
+                }
+            };
+            entries.push((keys, next_node));
+        }
+        Menu {
+            name: name.to_string(),
+            display_name,
+            entries,
+            settings,
+        }
+    }
+
+    fn parse_menu_settings(&mut self, p: Pair<'_, Rule>, file: &Path) -> Vec<MenuSetting> {
+        let mut res = vec![];
+        for pair in p.into_inner() {
+            assert!(pair.as_rule() == Rule::symbol);
+            match pair.as_str() {
+                "sort_by_key" => res.push(MenuSetting::SortByKey),
+                "sort_by_name" => res.push(MenuSetting::SortByName),
+                other => self.errors.push(error_at(
+                    pair.as_span(),
+                    file,
+                    format!("invalid menu setting: {other}"),
+                )),
+            }
+        }
+        res
+    }
+
+    fn parse_anon_command(&mut self, p: Pair<'_, Rule>, file: &Path) -> Command {
+        let mut children = p.into_inner();
+        let mut body = children.next().unwrap();
+        let mut id = None;
+        if body.as_rule() == Rule::command_id {
+            id = Some(body.as_str().to_string());
+            body = children.next().unwrap();
+        }
+        let mut elems = body.into_inner();
+        let mut parser = CmdBodyParser::default();
+        loop {
+            let p = elems.next().unwrap();
+            if let Some(cmd) = parser.parse(p, self, file) {
+                break Command { id, ..cmd };
+            }
+        }
+    }
+
+    fn parse_cmd_settings(&mut self, p: Pair<'_, Rule>, file: &Path) -> Vec<CommandSetting> {
+        let mut res = vec![];
+        let mut show_output = None;
+        for pair in p.into_inner() {
+            let pair = pair.inext();
+            match pair.as_rule() {
+                Rule::args_setting => {
+                    let mode = pair.inext();
+                    match mode.as_str() {
+                        "env" => res.push(CommandSetting::Args(ArgsMode::Env)),
+                        "positional" => res.push(CommandSetting::Args(ArgsMode::Positional)),
+                        other => self.errors.push(error_at(
+                            mode.as_span(),
+                            file,
+                            format!("invalid args mode: {other}, expected env or positional"),
+                        )),
+                    }
+                }
+                Rule::output_setting => {
+                    show_output = Some(pair.as_span());
+                    let n_lines = match pair.into_inner().next() {
+                        Some(n) => match n.as_str().parse() {
+                            Ok(n) => n,
+                            Err(_) => {
+                                self.errors
+                                    .push(error_at(n.as_span(), file, "number too large"));
+                                continue;
+                            }
+                        },
+                        None => DEFAULT_OUTPUT_LINES,
+                    };
+                    res.push(CommandSetting::ShowOutput(n_lines));
+                }
+                Rule::symbol => match pair.as_str() {
+                    "repeat" => res.push(CommandSetting::Repeat),
+                    "ignore_result" => res.push(CommandSetting::IgnoreResult),
+                    "stay" => res.push(CommandSetting::Stay),
+                    "confirm" => res.push(CommandSetting::Confirm),
+                    "cd" | "export" => {
+                        let effect = match pair.as_str() {
+                            "cd" => ShellEffect::Cd,
+                            _ => ShellEffect::Export,
+                        };
+                        if res.iter().any(|s| matches!(s, CommandSetting::Effect(_))) {
+                            self.errors.push(error_at(
+                                pair.as_span(),
+                                file,
+                                "cd and export can't be combined",
+                            ));
+                        } else {
+                            res.push(CommandSetting::Effect(effect));
+                        }
+                    }
+                    other => self.errors.push(error_at(
+                        pair.as_span(),
+                        file,
+                        format!("invalid command setting: {other}"),
+                    )),
+                },
+                _ => panic!("unexpected rule: {pair:#?}"),
+            }
+        }
+        if let Some(span) = show_output {
+            if !res.contains(&CommandSetting::Repeat) {
+                self.warnings.push(error_at(
+                    span,
+                    file,
+                    "show_output only has an effect on repeat commands",
+                ));
+            }
+        }
+        res
+    }
+
+    fn parse_quick_command(
+        &mut self,
+        pair: Pair<'_, Rule>,
+        file: &Path,
+    ) -> (Option<String>, StringExpr) {
+        assert!(pair.as_rule() == Rule::quick_command);
+        let elems: Vec<_> = pair.into_inner().collect();
+        match elems.len() {
+            1 => (None, self.parse_string_expr(elems[0].clone(), file)),
+            2 => (
+                Some(from_string(elems[0].clone())),
+                self.parse_string_expr(elems[1].clone(), file),
+            ),
+            _ => panic!("unexpected amount of string"),
+        }
+    }
+
+    fn parse_vars_def(&mut self, p: Pair<'_, Rule>, file: &Path) -> Vec<VarDef> {
+        assert!(p.as_rule() == Rule::vars_def);
+        p.into_inner()
+            .map(|p| {
+                assert!(p.as_rule() == Rule::var_def, "unexpected rule: {p:#?}");
+                let mut elems = p.into_inner();
+                let mut var = VarDef {
+                    name: elems.next().unwrap().as_str().to_string(),
+                    var_type: VarType::String,
+                    source: None,
+                    default: None,
+                    prompt: None,
+                };
+                for elem in elems {
+                    match elem.as_rule() {
+                        Rule::var_type => var.var_type = self.parse_var_type(elem.inext(), file),
+                        Rule::var_source => {
+                            let mut multi = false;
+                            for e in elem.into_inner() {
+                                match e.as_rule() {
+                                    Rule::multi_select => multi = true,
+                                    Rule::string_expr => {
+                                        var.source = Some(VarSource {
+                                            cmd: self.parse_string_expr(e, file),
+                                            multi,
+                                        })
+                                    }
+                                    _ => panic!("unexpected rule: {e:#?}"),
+                                }
+                            }
+                        }
+                        Rule::var_default => {
+                            let default = elem.inext();
+                            var.default = Some(match default.as_rule() {
+                                Rule::env_var => VarDefault::Env(default.inext().as_str().into()),
+                                Rule::string_expr => {
+                                    VarDefault::Expr(self.parse_string_expr(default, file))
+                                }
+                                _ => panic!("unexpected rule: {default:#?}"),
+                            });
+                        }
+                        Rule::var_prompt => var.prompt = Some(from_string(elem.inext())),
+                        _ => panic!("unexpected rule: {elem:#?}"),
+                    }
+                }
+                var
+            })
+            .collect()
+    }
+
+    fn parse_var_type(&mut self, p: Pair<'_, Rule>, file: &Path) -> VarType {
+        match p.as_rule() {
+            Rule::path_type => {
+                VarType::Path(p.into_inner().next().map(|check| match check.as_str() {
+                    "existing" => PathCheck::Existing,
+                    _ => PathCheck::New,
+                }))
             }
-            Rule::anon_command => Node::Command(parse_anon_command(child_pair)),
-            _ => {
-                panic!("unexpected rule: {child_pair:?}")
+            Rule::int_type => {
+                let (mut min, mut max) = (None, None);
+                if let Some(range) = p.into_inner().next() {
+                    let span = range.as_span();
+                    // None until the range operator was parsed, afterwards whether it's inclusive
+                    let mut inclusive = None;
+                    for elem in range.into_inner() {
+                        match elem.as_rule() {
+                            Rule::range_op => inclusive = Some(elem.as_str() == "..="),
+                            Rule::int_lit => {
+                                let Ok(val) = elem.as_str().parse::<i64>() else {
+                                    self.errors.push(error_at(
+                                        elem.as_span(),
+                                        file,
+                                        "integer too large",
+                                    ));
+                                    continue;
+                                };
+                                match inclusive {
+                                    None => min = Some(val),
+                                    Some(true) => max = Some(val),
+                                    Some(false) => match val.checked_sub(1) {
+                                        Some(val) => max = Some(val),
+                                        None => self.errors.push(error_at(
+                                            span,
+                                            file,
+                                            format!("the range {} is empty", span.as_str()),
+                                        )),
+                                    },
+                                }
+                            }
+                            _ => panic!("unexpected rule: {elem:#?}"),
+                        }
+                    }
+                    if matches!((min, max), (Some(min), Some(max)) if min > max) {
+                        self.errors.push(error_at(
+                            span,
+                            file,
+                            format!("the range {} is empty", span.as_str()),
+                        ));
+                    }
+                }
+                VarType::Int { min, max }
+            }
+            Rule::bool_type => VarType::Bool,
+            Rule::choice_type => VarType::Choice(
+                p.into_inner()
+                    .map(|choice| {
+                        let choice = choice.inext();
+                        match choice.as_rule() {
+                            Rule::string => from_string(choice),
+                            _ => choice.as_str().to_string(),
+                        }
+                    })
+                    .collect(),
+            ),
+            _ => panic!("unexpected rule: {p:#?}"),
+        }
+    }
+
+    /// Like [parse_string_expr], but additionally reports references to undefined snippets
+    fn parse_string_expr(&mut self, p: Pair<'_, Rule>, file: &Path) -> StringExpr {
+        for e in p.clone().into_inner() {
+            let elem = e.inext();
+            if elem.as_rule() == Rule::snippet_symbol {
+                let name = &elem.as_str()[1..];
+                self.used_snippets.insert(name.to_string());
+                if !self.snippets.contains_key(name) {
+                    self.errors.push(error_at(
+                        elem.as_span(),
+                        file,
+                        format!("undefined snippet: {name}"),
+                    ));
+                }
             }
+        }
+        parse_string_expr(p)
+    }
+
+    /// Warns about the `{{name}}` placeholders in the script of a command with vars, that don't
+    /// refer to one of them. They are left as they are, as braces also occur in scripts, e.g. in Go
+    /// templates. Placeholders in snippets are only known once they are resolved, so they aren't
+    /// checked
+    fn check_placeholders(&mut self, quick_command: &Pair<'_, Rule>, vars: &[VarDef], file: &Path) {
+        let Some(expr) = quick_command.clone().into_inner().last() else {
+            return;
         };
-        entries.insert(keys, next_node);
+        for elem in expr.into_inner() {
+            let elem = elem.inext();
+            if elem.as_rule() != Rule::string {
+                continue;
+            }
+            let content = elem.nnext(2);
+            for (range, name) in placeholders(content.as_str()) {
+                if !vars.iter().any(|v| v.name == name) {
+                    self.warnings.push(error_at(
+                        content.as_span().get(range).unwrap(),
+                        file,
+                        format!("{{{{{name}}}}} doesn't refer to a var of this command"),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Warns about an entry that can't be reached, because its keys start with the keys of an
+    /// entry before it, or vice versa. `find_submenus_for` always returns the shorter one.
+    fn check_keydef(&mut self, keydef: &Pair<'_, Rule>, previous: &[Pair<'_, Rule>], file: &Path) {
+        let keys = keydef.as_str();
+        for prev in previous {
+            let prev_keys = prev.as_str();
+            let (unreachable, message) = if prev_keys == keys {
+                (keydef, format!("key {keys} is already used in this menu"))
+            } else if keys.starts_with(prev_keys) {
+                (
+                    keydef,
+                    format!("entry {keys} is unreachable, because {prev_keys} is a prefix of it"),
+                )
+            } else if prev_keys.starts_with(keys) {
+                (
+                    prev,
+                    format!("entry {prev_keys} is unreachable, because {keys} is a prefix of it"),
+                )
+            } else {
+                continue;
+            };
+            self.warnings
+                .push(error_at(unreachable.as_span(), file, message));
+        }
+    }
+
+    /// Collects the warnings that can only be determined after the whole config was parsed
+    fn lint(&mut self, snippet_table: &SnippetTable) {
+        for (name, menu) in &self.menus {
+            if !self.menu_ids[..self.reachable_menus]
+                .iter()
+                .any(|n| n == name)
+            {
+                // it might be meant to be opened with --menu
+                self.notes.push(error_at(
+                    menu.name,
+                    menu.file,
+                    format!(
+                        "note: menu {name} is not reachable from the root menu, so it can only \
+                        be opened with --menu {name}"
+                    ),
+                ));
+            }
+        }
+
+        let mut snippets: Vec<_> = self.snippets.iter().collect();
+        snippets.sort_by_key(|(_, def)| (def.file, def.name.start()));
+        let mut in_cycle = HashSet::new();
+        for (name, def) in snippets {
+            if !self.used_snippets.contains(*name) {
+                self.warnings.push(error_at(
+                    def.name,
+                    def.file,
+                    format!("snippet {name} is never used"),
+                ));
+            }
+            if in_cycle.contains(*name) {
+                continue;
+            }
+            let mut path = vec![name.to_string()];
+            if find_snippet_cycle(snippet_table, &mut path) {
+                self.warnings.push(error_at(
+                    def.name,
+                    def.file,
+                    format!("snippet cycle: {}", path.join(" -> ")),
+                ));
+                in_cycle.extend(path);
+            }
+        }
     }
-    Ok(Menu {
-        name: name.to_string(),
-        display_name,
-        entries,
-    })
 }
 
-fn parse_anon_command(p: Pair<'_, Rule>) -> Command {
-    let body = p.inext();
-    let mut elems = body.into_inner();
-    let mut parser = CmdBodyParser::default();
-    loop {
-        let p = elems.next().unwrap();
-        if let Some(cmd) = parser.parse(p) {
-            break cmd;
+/// Searches for a chain of snippet references that leads from the first element of `path` back
+/// to itself. If one is found, true is returned and `path` contains the cycle.
+fn find_snippet_cycle(snippet_table: &SnippetTable, path: &mut Vec<String>) -> bool {
+    let Some(snippet) = snippet_table.get(path.last().unwrap()) else {
+        return false;
+    };
+    for elem in &snippet.0 {
+        let StringExprElem::Symbol(symbol) = elem else {
+            continue;
+        };
+        if *symbol == path[0] {
+            path.push(symbol.clone());
+            return true;
+        }
+        // cycles that don't contain the start are found when starting from one of their members
+        if path.contains(symbol) {
+            continue;
         }
+        path.push(symbol.clone());
+        if find_snippet_cycle(snippet_table, path) {
+            return true;
+        }
+        path.pop();
     }
+    false
+}
+
+/// Sorts errors by their file and location
+fn sorted(mut errors: Vec<Error<Rule>>) -> Vec<Error<Rule>> {
+    errors.sort_by_key(|e| {
+        let start = match e.location {
+            InputLocation::Pos(pos) => pos,
+            InputLocation::Span((start, _)) => start,
+        };
+        (e.path().map(str::to_string), start)
+    });
+    errors
 }
 
 #[derive(Default)]
 struct CmdBodyParser {
     settings: Option<Vec<CommandSetting>>,
-    vars: Option<Vec<String>>,
+    vars: Option<Vec<VarDef>>,
     shell_def: Option<ShellDef>,
 }
 
 impl CmdBodyParser {
-    fn parse(&mut self, p: Pair<'_, Rule>) -> Option<Command> {
+    fn parse(
+        &mut self,
+        p: Pair<'_, Rule>,
+        builder: &mut ConfigBuilder<'_>,
+        file: &Path,
+    ) -> Option<Command> {
         match p.as_rule() {
             Rule::cmd_settings => {
-                self.settings = Some(parse_cmd_settings(p));
+                self.settings = Some(builder.parse_cmd_settings(p, file));
                 None
             }
             Rule::vars_def => {
-                self.vars = Some(parse_vars_def(p));
+                self.vars = Some(builder.parse_vars_def(p, file));
                 None
             }
             Rule::shell_def => {
@@ -251,13 +1106,19 @@ impl CmdBodyParser {
                 None
             }
             Rule::quick_command => {
-                let (display_name, exec_str) = parse_quick_command(p);
+                if let Some(vars) = &self.vars {
+                    builder.check_placeholders(&p, vars, file);
+                }
+                let (display_name, exec_str) = builder.parse_quick_command(p, file);
                 Some(Command {
+                    id: None,
                     exec_str,
                     settings: self.settings.take().unwrap_or_default(),
                     name: display_name,
                     env_vars: self.vars.take().unwrap_or_default(),
                     shell: self.shell_def.take(),
+                    // set by the menu, which knows where the entry starts
+                    location: Location::default(),
                 })
             }
             _ => panic!("unexpected rule: {p:#?}"),
@@ -265,42 +1126,27 @@ impl CmdBodyParser {
     }
 }
 
-fn parse_cmd_settings(p: Pair<'_, Rule>) -> Vec<CommandSetting> {
+/// Finds the `{{name}}` placeholders in `script`, where name could be the name of a var, and
+/// returns their byte ranges and names, from left to right
+pub(crate) fn placeholders(script: &str) -> Vec<(Range<usize>, &str)> {
     let mut res = vec![];
-    for pair in p.into_inner() {
-        assert!(pair.as_rule() == Rule::symbol);
-        res.push(match pair.as_str() {
-            "repeat" => CommandSetting::Repeat,
-            "ignore_result" => CommandSetting::IgnoreResult,
-            other => panic!("invalid command setting: {other}"),
-        })
+    let mut pos = 0;
+    while let Some(start) = script[pos..].find("{{").map(|i| pos + i) {
+        let name_start = start + 2;
+        let name_end = script[name_start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(script.len(), |i| name_start + i);
+        if name_end > name_start && script[name_end..].starts_with("}}") {
+            res.push((start..name_end + 2, &script[name_start..name_end]));
+            pos = name_end + 2;
+        } else {
+            // e.g. {{{name}}}, where the placeholder starts at the next brace
+            pos = start + 1;
+        }
     }
     res
 }
 
-fn parse_vars_def(p: Pair<'_, Rule>) -> Vec<String> {
-    assert!(p.as_rule() == Rule::vars_def);
-    p.into_inner()
-        .map(|p| {
-            assert!(p.as_rule() == Rule::var_def, "unexpected rule: {p:#?}");
-            p.inext().as_str().to_string()
-        })
-        .collect()
-}
-
-fn parse_quick_command(pair: Pair<'_, Rule>) -> (Option<String>, StringExpr) {
-    assert!(pair.as_rule() == Rule::quick_command);
-    let elems: Vec<_> = pair.into_inner().collect();
-    match elems.len() {
-        1 => (None, parse_string_expr(elems[0].clone())),
-        2 => (
-            Some(from_string(elems[0].clone())),
-            parse_string_expr(elems[1].clone()),
-        ),
-        _ => panic!("unexpected amount of string"),
-    }
-}
-
 fn parse_string_expr(p: Pair<'_, Rule>) -> StringExpr {
     let mut res = vec![];
     for e in p.into_inner() {
@@ -317,11 +1163,42 @@ fn parse_string_expr(p: Pair<'_, Rule>) -> StringExpr {
     StringExpr(res)
 }
 
-impl std::fmt::Display for Node {
+/// Creates an error that points to `span` within `file`
+fn error_at(span: Span<'_>, file: &Path, message: impl Into<String>) -> Error<Rule> {
+    let err = Error::new_from_span(
+        ErrorVariant::CustomError {
+            message: message.into(),
+        },
+        span,
+    );
+    with_path(err, file)
+}
+
+fn with_path(err: Error<Rule>, file: &Path) -> Error<Rule> {
+    if file.as_os_str().is_empty() {
+        err
+    } else {
+        err.with_path(&file.to_string_lossy())
+    }
+}
+
+impl std::fmt::Display for Menu {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Menu(m) => write!(f, "{}", m.display_name.as_ref().unwrap_or(&m.name)),
-            Self::Command(c) => write!(f, "{c}"),
+        write!(f, "{}", self.display_name.as_ref().unwrap_or(&self.name))
+    }
+}
+
+/// Displays a [Node] by looking up the name of submenus. Created with [Node::display]
+pub struct NodeDisplay<'a> {
+    node: &'a Node,
+    menus: &'a Menus,
+}
+
+impl std::fmt::Display for NodeDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.node {
+            Node::Menu(id) => write!(f, "{}", self.menus[*id]),
+            Node::Command(c) => write!(f, "{c}"),
         }
     }
 }
@@ -350,43 +1227,248 @@ impl std::fmt::Display for Command {
     }
 }
 
-impl Command {
-    pub fn repeat(&self) -> bool {
-        self.settings.contains(&CommandSetting::Repeat)
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.col)
     }
 }
 
-impl Default for ShellDef {
-    fn default() -> Self {
-        #[cfg(not(windows))]
-        let res = ShellDef {
-            name: "bash".into(),
-            args: vec!["-euo".into(), "pipefail".into(), "-c".into()],
-        };
-
-        #[cfg(windows)]
-        let res = ShellDef {
-            name: "cmd".into(),
-            args: vec!["/c".into()],
-        };
+impl std::fmt::Display for ShellDef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        for arg in &self.args {
+            write!(f, " {arg}")?;
+        }
+        Ok(())
+    }
+}
 
-        res
+/// Displays the setting as it is written in the config
+impl std::fmt::Display for CommandSetting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandSetting::Repeat => write!(f, "repeat"),
+            CommandSetting::IgnoreResult => write!(f, "ignore_result"),
+            CommandSetting::Stay => write!(f, "stay"),
+            CommandSetting::Confirm => write!(f, "confirm"),
+            CommandSetting::ShowOutput(n_lines) => write!(f, "show_output {n_lines}"),
+            CommandSetting::Args(ArgsMode::Env) => write!(f, "args env"),
+            CommandSetting::Args(ArgsMode::Positional) => write!(f, "args positional"),
+            CommandSetting::Effect(ShellEffect::Cd) => write!(f, "cd"),
+            CommandSetting::Effect(ShellEffect::Export) => write!(f, "export"),
+        }
     }
 }
 
-impl ShellDef {
-    pub fn args_with<'a>(&'a self, additional_arg: &'a str) -> Vec<&'a str> {
-        self.args
-            .iter()
-            .map(String::as_str)
-            .chain(std::iter::once(additional_arg))
-            .collect()
+/// Displays the var as it is written in the config
+impl std::fmt::Display for VarDef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        match &self.var_type {
+            VarType::String => {}
+            VarType::Path(None) => write!(f, ": path")?,
+            VarType::Path(Some(PathCheck::Existing)) => write!(f, ": path existing")?,
+            VarType::Path(Some(PathCheck::New)) => write!(f, ": path new")?,
+            VarType::Int { min, max } => {
+                write!(f, ": int")?;
+                if min.is_some() || max.is_some() {
+                    let min = min.map(|m| m.to_string()).unwrap_or_default();
+                    let max = max.map(|m| m.to_string()).unwrap_or_default();
+                    write!(f, " {min}..={max}")?;
+                }
+            }
+            VarType::Bool => write!(f, ": bool")?,
+            VarType::Choice(choices) => write!(f, ": choice [{}]", choices.join(", "))?,
+        }
+        if let Some(source) = &self.source {
+            let multi = if source.multi { "multi " } else { "" };
+            write!(f, " from {multi}{}", source.cmd)?;
+        }
+        match &self.default {
+            Some(VarDefault::Expr(expr)) => write!(f, " = {expr}")?,
+            Some(VarDefault::Env(name)) => write!(f, " = env({name})")?,
+            None => {}
+        }
+        if let Some(prompt) = &self.prompt {
+            write!(f, " {prompt:?}")?;
+        }
+        Ok(())
     }
 }
 
-impl StringExpr {
-    pub fn resolve(&self, snippet_table: &SnippetTable) -> Result<String> {
-        self.inner_resolve(snippet_table, vec![])
+impl std::fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let errors: Vec<_> = self.0.iter().map(|e| e.to_string()).collect();
+        write!(f, "{}", errors.join("\n\n"))
+    }
+}
+
+impl std::error::Error for ParseErrors {}
+
+impl Node {
+    pub fn display<'a>(&'a self, menus: &'a Menus) -> NodeDisplay<'a> {
+        NodeDisplay { node: self, menus }
+    }
+}
+
+impl Menu {
+    /// The entries in the order they should be displayed in, which is the declaration order,
+    /// unless the menu is configured to sort them
+    pub fn sorted_entries<'a>(&'a self, menus: &Menus) -> Vec<&'a (Vec<char>, Node)> {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        if self.settings.contains(&MenuSetting::SortByKey) {
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        } else if self.settings.contains(&MenuSetting::SortByName) {
+            entries.sort_by_cached_key(|(_, node)| node.display(menus).to_string());
+        }
+        entries
+    }
+}
+
+impl Menus {
+    pub fn root(&self) -> MenuId {
+        MenuId(0)
+    }
+
+    /// The id of the menu that is declared with the symbol `name`
+    pub fn find(&self, name: &str) -> Option<MenuId> {
+        self.0.iter().position(|m| m.name == name).map(MenuId)
+    }
+}
+
+impl std::ops::Index<MenuId> for Menus {
+    type Output = Menu;
+
+    fn index(&self, id: MenuId) -> &Menu {
+        &self.0[id.0]
+    }
+}
+
+impl Command {
+    pub fn repeat(&self) -> bool {
+        self.settings.contains(&CommandSetting::Repeat)
+    }
+
+    /// how many lines of output should be shown, if any
+    pub fn show_output(&self) -> Option<usize> {
+        self.settings.iter().find_map(|s| match s {
+            CommandSetting::ShowOutput(n_lines) => Some(*n_lines),
+            _ => None,
+        })
+    }
+
+    pub fn confirm(&self) -> bool {
+        self.settings.contains(&CommandSetting::Confirm)
+    }
+
+    pub fn stay(&self) -> bool {
+        self.settings.contains(&CommandSetting::Stay)
+    }
+
+    pub fn shell_effect(&self) -> Option<ShellEffect> {
+        self.settings.iter().find_map(|s| match s {
+            CommandSetting::Effect(effect) => Some(*effect),
+            _ => None,
+        })
+    }
+
+    pub fn args_mode(&self) -> ArgsMode {
+        self.settings
+            .iter()
+            .rev()
+            .find_map(|s| match s {
+                CommandSetting::Args(mode) => Some(*mode),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Default for ShellDef {
+    fn default() -> Self {
+        #[cfg(not(windows))]
+        let res = ShellDef {
+            name: "bash".into(),
+            args: vec!["-euo".into(), "pipefail".into(), "-c".into()],
+        };
+
+        #[cfg(windows)]
+        let res = ShellDef {
+            name: "cmd".into(),
+            args: vec!["/c".into()],
+        };
+
+        res
+    }
+}
+
+impl ShellDef {
+    pub fn args_with<'a>(&'a self, additional_arg: &'a str) -> Vec<&'a str> {
+        self.args
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(additional_arg))
+            .collect()
+    }
+}
+
+impl VarType {
+    /// Checks whether `value` is valid for this type and returns it in its normalized form
+    pub fn validate(&self, value: &str) -> Result<String> {
+        match self {
+            VarType::String => Ok(value.to_string()),
+            VarType::Path(check) => {
+                let exists = Path::new(value).exists();
+                match check {
+                    Some(PathCheck::Existing) => ensure!(exists, "{value} doesn't exist"),
+                    Some(PathCheck::New) => ensure!(!exists, "{value} already exists"),
+                    None => {}
+                }
+                Ok(value.to_string())
+            }
+            VarType::Int { min, max } => {
+                let val: i64 = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("{value} is not an integer"))?;
+                if let Some(min) = min {
+                    ensure!(val >= *min, "{val} is less than {min}");
+                }
+                if let Some(max) = max {
+                    ensure!(val <= *max, "{val} is greater than {max}");
+                }
+                Ok(val.to_string())
+            }
+            VarType::Bool => match value.trim().to_lowercase().as_str() {
+                "y" | "yes" | "true" => Ok("true".into()),
+                "n" | "no" | "false" => Ok("false".into()),
+                _ => Err(anyhow!("expected y or n, got {value}")),
+            },
+            VarType::Choice(choices) => {
+                ensure!(
+                    choices.iter().any(|c| c == value),
+                    "expected one of {}, got {value}",
+                    choices.join(", ")
+                );
+                Ok(value.to_string())
+            }
+        }
+    }
+}
+
+impl VarDefault {
+    /// Returns the default value, or None if it refers to an environment variable that isn't set
+    pub fn resolve(&self, snippet_table: &SnippetTable) -> Result<Option<String>> {
+        Ok(match self {
+            VarDefault::Expr(expr) => Some(expr.resolve(snippet_table)?),
+            VarDefault::Env(name) => std::env::var(name).ok(),
+        })
+    }
+}
+
+impl StringExpr {
+    pub fn resolve(&self, snippet_table: &SnippetTable) -> Result<String> {
+        self.inner_resolve(snippet_table, vec![])
     }
 
     fn inner_resolve(&self, snippet_table: &SnippetTable, parents: Vec<String>) -> Result<String> {
@@ -416,6 +1498,7 @@ impl StringExpr {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
 
     const CONF: &str = r#"
         menu root {
@@ -469,6 +1552,135 @@ mod tests {
         }
     "#;
 
+    const MULTIPLE_ERRORS: &str = r#"
+        snippet a = $missing_snippet + "echo a"
+
+        menu root {
+            a: cmd {
+                set repet
+                $a
+            }
+            b: missing
+        }
+    "#;
+
+    const SYNTAX_ERROR: &str = r#"
+        menu root {
+            a "echo a"
+        }
+    "#;
+
+    const SORTED_MENUS: &str = r#"
+        menu root {
+            set sort_by_key
+            n: by_name
+            d: "echo declared"
+        }
+
+        menu by_name {
+            set sort_by_name
+            b: "a name" - "echo a"
+            a: "b name" - "echo b"
+        }
+    "#;
+
+    const RECURSIVE_MENUS: &str = r#"
+        menu root {
+            a: a
+            b: b
+        }
+
+        menu a {
+            b: b
+        }
+
+        menu b {
+            a: a
+            r: root
+        }
+    "#;
+
+    const VAR_DEFAULTS: &str = r#"
+        snippet main = "main"
+
+        menu root {
+            c: cmd {
+                vars branch = $main "Branch to create",
+                    home = env(HOME),
+                    dir "Output directory"
+                "git worktree add -b $branch $dir"
+            }
+        }
+    "#;
+
+    const VAR_TYPES: &str = r#"
+        menu root {
+            c: cmd {
+                vars file: path existing,
+                    out: path new,
+                    any: path,
+                    jobs: int 1..=16,
+                    level: int ..3,
+                    force: bool = "no",
+                    env: choice [dev, staging, "prod eu"]
+                "echo $file $out $jobs $level $force $env"
+            }
+        }
+    "#;
+
+    const VAR_SOURCES: &str = r#"
+        menu root {
+            c: cmd {
+                vars branch from "git branch --format=%(refname:short)" = "main",
+                    files from multi "git ls-files" "Files to stage"
+                "git switch $branch && git add $files"
+            }
+        }
+    "#;
+
+    const ARGS_MODES: &str = r#"
+        menu root {
+            p: cmd {
+                set repeat, args positional
+                vars a
+                "echo $1"
+            }
+            e: cmd {
+                set args env
+                vars a
+                "echo $a"
+            }
+            d: cmd {
+                vars a
+                "echo {{a}}"
+            }
+        }
+    "#;
+
+    const SHELL_EFFECTS: &str = r#"
+        menu root {
+            c: cmd {
+                set cd
+                "echo /tmp"
+            }
+            e: cmd {
+                set stay, export
+                "echo FOO=bar"
+            }
+            n: "echo nothing"
+        }
+    "#;
+
+    const COMMAND_IDS: &str = r#"
+        menu root {
+            a: cmd amend { "git commit --amend" }
+            aa: cmd amend_all {
+                "git commit -a --amend"
+            }
+            s: "git status"
+        }
+    "#;
+
     const WITH_SETTING: &str = r#"
         menu root {
             a: cmd {
@@ -494,25 +1706,33 @@ mod tests {
             root,
             r#"
 Config {
-    menu: Menu {
-        name: "root",
-        display_name: None,
-        entries: {
-            [
-                'c',
-            ]: Menu(
-                Menu {
-                    name: "custom_commands",
-                    display_name: None,
-                    entries: {
+    menus: Menus(
+        [
+            Menu {
+                name: "root",
+                display_name: None,
+                entries: [
+                    (
                         [
                             'c',
-                        ]: Command(
+                        ],
+                        Menu(
+                            MenuId(
+                                1,
+                            ),
+                        ),
+                    ),
+                    (
+                        [
+                            'f',
+                        ],
+                        Command(
                             Command {
+                                id: None,
                                 exec_str: StringExpr(
                                     [
                                         String(
-                                            "echo ciao",
+                                            "echo "!",
                                         ),
                                     ],
                                 ),
@@ -520,12 +1740,28 @@ Config {
                                 name: None,
                                 shell: None,
                                 env_vars: [],
+                                location: Location {
+                                    file: "",
+                                    line: 4,
+                                    col: 13,
+                                },
                             },
                         ),
+                    ),
+                ],
+                settings: [],
+            },
+            Menu {
+                name: "custom_commands",
+                display_name: None,
+                entries: [
+                    (
                         [
                             'h',
-                        ]: Command(
+                        ],
+                        Command(
                             Command {
+                                id: None,
                                 exec_str: StringExpr(
                                     [
                                         String(
@@ -539,30 +1775,45 @@ Config {
                                 ),
                                 shell: None,
                                 env_vars: [],
+                                location: Location {
+                                    file: "",
+                                    line: 8,
+                                    col: 13,
+                                },
                             },
                         ),
-                    },
-                },
-            ),
-            [
-                'f',
-            ]: Command(
-                Command {
-                    exec_str: StringExpr(
+                    ),
+                    (
                         [
-                            String(
-                                "echo "!",
-                            ),
+                            'c',
                         ],
+                        Command(
+                            Command {
+                                id: None,
+                                exec_str: StringExpr(
+                                    [
+                                        String(
+                                            "echo ciao",
+                                        ),
+                                    ],
+                                ),
+                                settings: [],
+                                name: None,
+                                shell: None,
+                                env_vars: [],
+                                location: Location {
+                                    file: "",
+                                    line: 9,
+                                    col: 13,
+                                },
+                            },
+                        ),
                     ),
-                    settings: [],
-                    name: None,
-                    shell: None,
-                    env_vars: [],
-                },
-            ),
-        },
-    },
+                ],
+                settings: [],
+            },
+        ],
+    ),
     shell_def: None,
     snippet_table: {},
 }
@@ -573,16 +1824,17 @@ Config {
 
     #[test]
     fn test_missing_ident() -> Result<()> {
-        let root = parse(MISSING_IDENT);
         k9::snapshot!(
-            root,
+            error_lines(MISSING_IDENT),
             r#"
-Err(
-    Error {
-        context: "Parsing submenu: missing",
-        source: "Undefined symbol: missing",
-    },
-)
+[
+    " --> 3:16",
+    "  |",
+    "3 |             s: missing",
+    "  |                ^-----^",
+    "  |",
+    "  = undefined menu: missing",
+]
 "#
         );
         Ok(())
@@ -590,123 +1842,832 @@ Err(
 
     #[test]
     fn test_no_root() -> Result<()> {
-        let root = parse(NO_ROOT);
         k9::snapshot!(
-            root,
+            error_lines(NO_ROOT),
             r#"
-Err(
-    "Undefined symbol: root",
-)
+[
+    " --> 1:1",
+    "  |",
+    "1 | ",
+    "  | ^",
+    "  |",
+    "  = missing root menu",
+]
 "#
         );
         Ok(())
     }
 
     #[test]
-    fn anon_cmd() -> Result<()> {
-        let root = parse(ANON_CMD);
+    fn multiple_errors() {
         k9::snapshot!(
-            root,
+            error_lines(MULTIPLE_ERRORS),
             r#"
-Ok(
-    Config {
-        menu: Menu {
-            name: "root",
-            display_name: None,
-            entries: {
-                [
-                    'c',
-                ]: Command(
-                    Command {
-                        exec_str: StringExpr(
-                            [
-                                String(
-                                    "echo foo",
-                                ),
-                            ],
-                        ),
-                        settings: [],
-                        name: None,
-                        shell: None,
-                        env_vars: [],
-                    },
-                ),
-            },
-        },
-        shell_def: None,
-        snippet_table: {},
-    },
-)
+[
+    " --> 2:21",
+    "  |",
+    "2 |         snippet a = $missing_snippet + "echo a"",
+    "  |                     ^--------------^",
+    "  |",
+    "  = undefined snippet: missing_snippet",
+    "",
+    " --> 6:21",
+    "  |",
+    "6 |                 set repet",
+    "  |                     ^---^",
+    "  |",
+    "  = invalid command setting: repet",
+    "",
+    " --> 9:16",
+    "  |",
+    "9 |             b: missing",
+    "  |                ^-----^",
+    "  |",
+    "  = undefined menu: missing",
+]
 "#
         );
-        Ok(())
     }
 
     #[test]
-    fn anon_cmd_2_args() -> Result<()> {
-        let root = parse(ANON_CMD2)?;
+    fn syntax_error() {
         k9::snapshot!(
-            root,
+            error_lines(SYNTAX_ERROR),
             r#"
-Config {
-    menu: Menu {
-        name: "root",
-        display_name: None,
-        entries: {
-            [
-                'c',
-            ]: Command(
-                Command {
-                    exec_str: StringExpr(
-                        [
-                            String(
-                                "echo $foo $bar",
-                            ),
-                        ],
-                    ),
-                    settings: [],
-                    name: None,
-                    shell: None,
-                    env_vars: [
-                        "foo",
-                        "bar",
-                    ],
-                },
-            ),
-        },
-    },
-    shell_def: None,
-    snippet_table: {},
-}
+[
+    " --> 3:13",
+    "  |",
+    "3 |             a "echo a"",
+    "  |             ^---",
+    "  |",
+    "  = expected menu_settings or entry",
+]
+"#
+        );
+    }
+
+    #[test]
+    fn sorted_menus() -> Result<()> {
+        let menus = parse(SORTED_MENUS)?.menus;
+        let root = &menus[menus.root()];
+        let Node::Menu(by_name) = root.entries[0].1 else {
+            panic!("expected a submenu");
+        };
+        let keys = |menu: &Menu| -> Vec<String> {
+            menu.sorted_entries(&menus)
+                .iter()
+                .map(|(keys, _)| String::from_iter(keys))
+                .collect()
+        };
+        k9::snapshot!(
+            (keys(root), keys(&menus[by_name])),
+            r#"
+(
+    [
+        "d",
+        "n",
+    ],
+    [
+        "b",
+        "a",
+    ],
+)
 "#
         );
         Ok(())
     }
 
     #[test]
-    fn named_menu() -> Result<()> {
-        let root = parse(NAMED_MENU)?;
+    fn recursive_menus() -> Result<()> {
+        let menus = parse(RECURSIVE_MENUS)?.menus;
         k9::snapshot!(
-            root,
+            menus,
             r#"
-Config {
-    menu: Menu {
-        name: "root",
-        display_name: None,
-        entries: {
-            [
-                'm',
-            ]: Menu(
+Menus(
+    [
+        Menu {
+            name: "root",
+            display_name: None,
+            entries: [
+                (
+                    [
+                        'a',
+                    ],
+                    Menu(
+                        MenuId(
+                            1,
+                        ),
+                    ),
+                ),
+                (
+                    [
+                        'b',
+                    ],
+                    Menu(
+                        MenuId(
+                            2,
+                        ),
+                    ),
+                ),
+            ],
+            settings: [],
+        },
+        Menu {
+            name: "a",
+            display_name: None,
+            entries: [
+                (
+                    [
+                        'b',
+                    ],
+                    Menu(
+                        MenuId(
+                            2,
+                        ),
+                    ),
+                ),
+            ],
+            settings: [],
+        },
+        Menu {
+            name: "b",
+            display_name: None,
+            entries: [
+                (
+                    [
+                        'a',
+                    ],
+                    Menu(
+                        MenuId(
+                            1,
+                        ),
+                    ),
+                ),
+                (
+                    [
+                        'r',
+                    ],
+                    Menu(
+                        MenuId(
+                            0,
+                        ),
+                    ),
+                ),
+            ],
+            settings: [],
+        },
+    ],
+)
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn var_defaults() -> Result<()> {
+        let config = parse(VAR_DEFAULTS)?;
+        let cmd = root_commands(&config)[0];
+        k9::snapshot!(
+            &cmd.env_vars,
+            r#"
+[
+    VarDef {
+        name: "branch",
+        var_type: String,
+        source: None,
+        default: Some(
+            Expr(
+                StringExpr(
+                    [
+                        Symbol(
+                            "main",
+                        ),
+                    ],
+                ),
+            ),
+        ),
+        prompt: Some(
+            "Branch to create",
+        ),
+    },
+    VarDef {
+        name: "home",
+        var_type: String,
+        source: None,
+        default: Some(
+            Env(
+                "HOME",
+            ),
+        ),
+        prompt: None,
+    },
+    VarDef {
+        name: "dir",
+        var_type: String,
+        source: None,
+        default: None,
+        prompt: Some(
+            "Output directory",
+        ),
+    },
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn var_types() -> Result<()> {
+        let config = parse(VAR_TYPES)?;
+        let cmd = root_commands(&config)[0];
+        let types: Vec<_> = cmd.env_vars.iter().map(|v| &v.var_type).collect();
+        k9::snapshot!(
+            types,
+            r#"
+[
+    Path(
+        Some(
+            Existing,
+        ),
+    ),
+    Path(
+        Some(
+            New,
+        ),
+    ),
+    Path(
+        None,
+    ),
+    Int {
+        min: Some(
+            1,
+        ),
+        max: Some(
+            16,
+        ),
+    },
+    Int {
+        min: None,
+        max: Some(
+            2,
+        ),
+    },
+    Bool,
+    Choice(
+        [
+            "dev",
+            "staging",
+            "prod eu",
+        ],
+    ),
+]
+"#
+        );
+
+        let validate = |i: usize, val: &str| types[i].validate(val).map_err(|e| e.to_string());
+        k9::snapshot!(
+            validate(0, "Cargo.toml"),
+            r#"
+Ok(
+    "Cargo.toml",
+)
+"#
+        );
+        k9::snapshot!(
+            validate(0, "does_not_exist"),
+            r#"
+Err(
+    "does_not_exist doesn't exist",
+)
+"#
+        );
+        k9::snapshot!(
+            validate(1, "Cargo.toml"),
+            r#"
+Err(
+    "Cargo.toml already exists",
+)
+"#
+        );
+        k9::snapshot!(
+            validate(3, " 4"),
+            r#"
+Ok(
+    "4",
+)
+"#
+        );
+        k9::snapshot!(
+            validate(3, "17"),
+            r#"
+Err(
+    "17 is greater than 16",
+)
+"#
+        );
+        k9::snapshot!(
+            validate(3, "four"),
+            r#"
+Err(
+    "four is not an integer",
+)
+"#
+        );
+        k9::snapshot!(
+            validate(4, "3"),
+            r#"
+Err(
+    "3 is greater than 2",
+)
+"#
+        );
+        k9::snapshot!(
+            validate(5, "Y"),
+            r#"
+Ok(
+    "true",
+)
+"#
+        );
+        k9::snapshot!(
+            validate(5, "maybe"),
+            r#"
+Err(
+    "expected y or n, got maybe",
+)
+"#
+        );
+        k9::snapshot!(
+            validate(6, "prod eu"),
+            r#"
+Ok(
+    "prod eu",
+)
+"#
+        );
+        k9::snapshot!(
+            validate(6, "test"),
+            r#"
+Err(
+    "expected one of dev, staging, prod eu, got test",
+)
+"#
+        );
+        k9::snapshot!(
+            error_lines(&VAR_TYPES.replace("int ..3", "int ..-9223372036854775808")),
+            r#"
+[
+    " --> 8:32",
+    "  |",
+    "8 |                     level: int ..-9223372036854775808,",
+    "  |                                ^--------------------^",
+    "  |",
+    "  = the range ..-9223372036854775808 is empty",
+]
+"#
+        );
+        k9::snapshot!(
+            error_lines(&VAR_TYPES.replace("int 1..=16", "int 16..=1")),
+            r#"
+[
+    " --> 7:31",
+    "  |",
+    "7 |                     jobs: int 16..=1,",
+    "  |                               ^----^",
+    "  |",
+    "  = the range 16..=1 is empty",
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn var_sources() -> Result<()> {
+        let config = parse(VAR_SOURCES)?;
+        let cmd = root_commands(&config)[0];
+        k9::snapshot!(
+            &cmd.env_vars,
+            r#"
+[
+    VarDef {
+        name: "branch",
+        var_type: String,
+        source: Some(
+            VarSource {
+                cmd: StringExpr(
+                    [
+                        String(
+                            "git branch --format=%(refname:short)",
+                        ),
+                    ],
+                ),
+                multi: false,
+            },
+        ),
+        default: Some(
+            Expr(
+                StringExpr(
+                    [
+                        String(
+                            "main",
+                        ),
+                    ],
+                ),
+            ),
+        ),
+        prompt: None,
+    },
+    VarDef {
+        name: "files",
+        var_type: String,
+        source: Some(
+            VarSource {
+                cmd: StringExpr(
+                    [
+                        String(
+                            "git ls-files",
+                        ),
+                    ],
+                ),
+                multi: true,
+            },
+        ),
+        default: None,
+        prompt: Some(
+            "Files to stage",
+        ),
+    },
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn args_modes() -> Result<()> {
+        let config = parse(ARGS_MODES)?;
+        let modes: Vec<_> = root_commands(&config)
+            .iter()
+            .map(|cmd| cmd.args_mode())
+            .collect();
+        k9::snapshot!(
+            modes,
+            r#"
+[
+    Positional,
+    Env,
+    Env,
+]
+"#
+        );
+        k9::snapshot!(
+            error_lines(&ARGS_MODES.replace("args env", "args named")),
+            r#"
+[
+    " --> 9:26",
+    "  |",
+    "9 |                 set args named",
+    "  |                          ^---^",
+    "  |",
+    "  = invalid args mode: named, expected env or positional",
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_placeholders() -> Result<()> {
+        k9::snapshot!(
+            warning_lines(&ARGS_MODES.replace("echo {{a}}", "echo {{a}} {{{b}}} {{.c}}")),
+            r#"
+[
+    "  --> 15:30",
+    "   |",
+    "15 |                 "echo {{a}} {{{b}}} {{.c}}"",
+    "   |                              ^---^",
+    "   |",
+    "   = {{b}} doesn't refer to a var of this command",
+]
+"#
+        );
+        let go_template = r#"
+            menu root {
+                p: "kubectl get pods -o go-template='{{range .items}}{{.metadata.name}}{{end}}'"
+                d: cmd {
+                    vars format
+                    "docker ps --format '{{format}}{{end}}'"
+                }
+            }
+        "#;
+        let config = parse(go_template)?;
+        let scripts: Vec<_> = root_commands(&config)
+            .iter()
+            .map(|cmd| cmd.exec_str.resolve(&config.snippet_table).unwrap())
+            .collect();
+        k9::snapshot!(
+            scripts,
+            r#"
+[
+    "kubectl get pods -o go-template='{{range .items}}{{.metadata.name}}{{end}}'",
+    "docker ps --format '{{format}}{{end}}'",
+]
+"#
+        );
+        k9::snapshot!(
+            warning_lines(go_template),
+            r#"
+[
+    " --> 6:52",
+    "  |",
+    "6 |                     "docker ps --format '{{format}}{{end}}'"",
+    "  |                                                    ^-----^",
+    "  |",
+    "  = {{end}} doesn't refer to a var of this command",
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn shell_effects() -> Result<()> {
+        let config = parse(SHELL_EFFECTS)?;
+        let effects: Vec<_> = root_commands(&config)
+            .iter()
+            .map(|cmd| cmd.shell_effect())
+            .collect();
+        k9::snapshot!(
+            effects,
+            r#"
+[
+    Some(
+        Cd,
+    ),
+    Some(
+        Export,
+    ),
+    None,
+]
+"#
+        );
+        k9::snapshot!(
+            error_lines(&SHELL_EFFECTS.replace("set cd", "set cd, export")),
+            r#"
+[
+    " --> 4:25",
+    "  |",
+    "4 |                 set cd, export",
+    "  |                         ^----^",
+    "  |",
+    "  = cd and export can't be combined",
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keyword_boundaries() {
+        k9::snapshot!(
+            error_lines(&ARGS_MODES.replace("args env", "argsenv")),
+            r#"
+[
+    " --> 9:21",
+    "  |",
+    "9 |                 set argsenv",
+    "  |                     ^-----^",
+    "  |",
+    "  = invalid command setting: argsenv",
+]
+"#
+        );
+        k9::snapshot!(
+            error_lines(&ARGS_MODES.replace("args positional", "show_output5")),
+            r#"
+[
+    " --> 4:29",
+    "  |",
+    "4 |                 set repeat, show_output5",
+    "  |                             ^----------^",
+    "  |",
+    "  = invalid command setting: show_output5",
+]
+"#
+        );
+        k9::snapshot!(
+            error_lines(&COMMAND_IDS.replace("cmd amend", "cmdamend")),
+            r#"
+[
+    " --> 3:25",
+    "  |",
+    "3 |             a: cmdamend { "git commit --amend" }",
+    "  |                         ^---",
+    "  |",
+    "  = expected entry",
+]
+"#
+        );
+    }
+
+    #[test]
+    fn command_ids() -> Result<()> {
+        let config = parse(COMMAND_IDS)?;
+        let ids: Vec<_> = root_commands(&config)
+            .iter()
+            .map(|cmd| cmd.id.clone())
+            .collect();
+        k9::snapshot!(
+            ids,
+            r#"
+[
+    Some(
+        "amend",
+    ),
+    Some(
+        "amend_all",
+    ),
+    None,
+]
+"#
+        );
+        k9::snapshot!(
+            error_lines(&COMMAND_IDS.replace("amend_all", "amend")),
+            r#"
+[
+    " --> 4:21",
+    "  |",
+    "4 |             aa: cmd amend {",
+    "  |                     ^---^",
+    "  |",
+    "  = command id amend is already used in this menu",
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn anon_cmd() -> Result<()> {
+        let root = parse(ANON_CMD);
+        k9::snapshot!(
+            root,
+            r#"
+Ok(
+    Config {
+        menus: Menus(
+            [
                 Menu {
-                    name: "menu2",
-                    display_name: Some(
-                        "2nd menu",
+                    name: "root",
+                    display_name: None,
+                    entries: [
+                        (
+                            [
+                                'c',
+                            ],
+                            Command(
+                                Command {
+                                    id: None,
+                                    exec_str: StringExpr(
+                                        [
+                                            String(
+                                                "echo foo",
+                                            ),
+                                        ],
+                                    ),
+                                    settings: [],
+                                    name: None,
+                                    shell: None,
+                                    env_vars: [],
+                                    location: Location {
+                                        file: "",
+                                        line: 3,
+                                        col: 13,
+                                    },
+                                },
+                            ),
+                        ),
+                    ],
+                    settings: [],
+                },
+            ],
+        ),
+        shell_def: None,
+        snippet_table: {},
+    },
+)
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn anon_cmd_2_args() -> Result<()> {
+        let root = parse(ANON_CMD2)?;
+        k9::snapshot!(
+            root,
+            r#"
+Config {
+    menus: Menus(
+        [
+            Menu {
+                name: "root",
+                display_name: None,
+                entries: [
+                    (
+                        [
+                            'c',
+                        ],
+                        Command(
+                            Command {
+                                id: None,
+                                exec_str: StringExpr(
+                                    [
+                                        String(
+                                            "echo $foo $bar",
+                                        ),
+                                    ],
+                                ),
+                                settings: [],
+                                name: None,
+                                shell: None,
+                                env_vars: [
+                                    VarDef {
+                                        name: "foo",
+                                        var_type: String,
+                                        source: None,
+                                        default: None,
+                                        prompt: None,
+                                    },
+                                    VarDef {
+                                        name: "bar",
+                                        var_type: String,
+                                        source: None,
+                                        default: None,
+                                        prompt: None,
+                                    },
+                                ],
+                                location: Location {
+                                    file: "",
+                                    line: 3,
+                                    col: 13,
+                                },
+                            },
+                        ),
+                    ),
+                ],
+                settings: [],
+            },
+        ],
+    ),
+    shell_def: None,
+    snippet_table: {},
+}
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn named_menu() -> Result<()> {
+        let root = parse(NAMED_MENU)?;
+        k9::snapshot!(
+            root,
+            r#"
+Config {
+    menus: Menus(
+        [
+            Menu {
+                name: "root",
+                display_name: None,
+                entries: [
+                    (
+                        [
+                            'm',
+                        ],
+                        Menu(
+                            MenuId(
+                                1,
+                            ),
+                        ),
                     ),
-                    entries: {
+                ],
+                settings: [],
+            },
+            Menu {
+                name: "menu2",
+                display_name: Some(
+                    "2nd menu",
+                ),
+                entries: [
+                    (
                         [
                             'f',
-                        ]: Command(
+                        ],
+                        Command(
                             Command {
+                                id: None,
                                 exec_str: StringExpr(
                                     [
                                         String(
@@ -718,13 +2679,19 @@ Config {
                                 name: None,
                                 shell: None,
                                 env_vars: [],
+                                location: Location {
+                                    file: "",
+                                    line: 7,
+                                    col: 13,
+                                },
                             },
                         ),
-                    },
-                },
-            ),
-        },
-    },
+                    ),
+                ],
+                settings: [],
+            },
+        ],
+    ),
     shell_def: None,
     snippet_table: {},
 }
@@ -740,31 +2707,45 @@ Config {
             root,
             r#"
 Config {
-    menu: Menu {
-        name: "root",
-        display_name: None,
-        entries: {
-            [
-                'a',
-            ]: Command(
-                Command {
-                    exec_str: StringExpr(
+    menus: Menus(
+        [
+            Menu {
+                name: "root",
+                display_name: None,
+                entries: [
+                    (
                         [
-                            String(
-                                "touch foo",
-                            ),
+                            'a',
                         ],
+                        Command(
+                            Command {
+                                id: None,
+                                exec_str: StringExpr(
+                                    [
+                                        String(
+                                            "touch foo",
+                                        ),
+                                    ],
+                                ),
+                                settings: [
+                                    Repeat,
+                                ],
+                                name: None,
+                                shell: None,
+                                env_vars: [],
+                                location: Location {
+                                    file: "",
+                                    line: 3,
+                                    col: 13,
+                                },
+                            },
+                        ),
                     ),
-                    settings: [
-                        Repeat,
-                    ],
-                    name: None,
-                    shell: None,
-                    env_vars: [],
-                },
-            ),
-        },
-    },
+                ],
+                settings: [],
+            },
+        ],
+    ),
     shell_def: None,
     snippet_table: {},
 }
@@ -780,32 +2761,46 @@ Config {
             root,
             r#"
 Config {
-    menu: Menu {
-        name: "root",
-        display_name: None,
-        entries: {
-            [
-                'a',
-            ]: Command(
-                Command {
-                    exec_str: StringExpr(
+    menus: Menus(
+        [
+            Menu {
+                name: "root",
+                display_name: None,
+                entries: [
+                    (
                         [
-                            String(
-                                "touch foo",
-                            ),
+                            'a',
                         ],
+                        Command(
+                            Command {
+                                id: None,
+                                exec_str: StringExpr(
+                                    [
+                                        String(
+                                            "touch foo",
+                                        ),
+                                    ],
+                                ),
+                                settings: [
+                                    Repeat,
+                                    IgnoreResult,
+                                ],
+                                name: None,
+                                shell: None,
+                                env_vars: [],
+                                location: Location {
+                                    file: "",
+                                    line: 3,
+                                    col: 13,
+                                },
+                            },
+                        ),
                     ),
-                    settings: [
-                        Repeat,
-                        IgnoreResult,
-                    ],
-                    name: None,
-                    shell: None,
-                    env_vars: [],
-                },
-            ),
-        },
-    },
+                ],
+                settings: [],
+            },
+        ],
+    ),
     shell_def: None,
     snippet_table: {},
 }
@@ -858,6 +2853,162 @@ StringExpr(
         ),
     ],
 )
+"#
+        );
+        Ok(())
+    }
+
+    fn error_lines(src: &str) -> Vec<String> {
+        let err = parse(src).unwrap_err();
+        err.to_string().lines().map(str::to_string).collect()
+    }
+
+    fn warning_lines(src: &str) -> Vec<String> {
+        let (_, findings) =
+            parse_sources(SourceFile::new(PathBuf::new(), src.to_string()), ROOT, true).unwrap();
+        findings
+            .warnings
+            .iter()
+            .flat_map(|w| {
+                w.to_string()
+                    .lines()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// the commands in the root menu of `config`, which must not contain submenus
+    fn root_commands(config: &Config) -> Vec<&Command> {
+        config.menus[config.menus.root()]
+            .entries
+            .iter()
+            .map(|(_, node)| match node {
+                Node::Command(cmd) => cmd,
+                Node::Menu(_) => panic!("expected a command"),
+            })
+            .collect()
+    }
+
+    /// writes `files` into a fresh directory below the systems temp dir, which is removed when
+    /// the returned handle is dropped
+    fn write_conf_dir(name: &str, files: &[(&str, &str)]) -> Result<TempDir> {
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("dotree_{name}_"))
+            .tempdir()?;
+        for (file_name, content) in files {
+            let path = dir.path().join(file_name);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, content)?;
+        }
+        Ok(dir)
+    }
+
+    #[test]
+    fn include() -> Result<()> {
+        let dir = write_conf_dir(
+            "include",
+            &[
+                (
+                    "main.dt",
+                    r#"
+                        include "sub/git.dt"
+                        menu root {
+                            g: git
+                        }
+                    "#,
+                ),
+                (
+                    "sub/git.dt",
+                    r#"
+                        import "snippets.dt"
+                        menu git {
+                            s: $status
+                        }
+                    "#,
+                ),
+                ("sub/snippets.dt", r#"snippet status = "git status""#),
+            ],
+        )?;
+        let conf = parse_file(&dir.path().join("main.dt"))?;
+        let root = &conf.menus[conf.menus.root()];
+        let Node::Menu(git) = root.entries[0].1 else {
+            panic!("expected a submenu");
+        };
+        let Node::Command(status) = &conf.menus[git].entries[0].1 else {
+            panic!("expected a command");
+        };
+        k9::snapshot!(
+            status.exec_str.resolve(&conf.snippet_table),
+            r#"
+Ok(
+    "git status",
+)
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn include_cycle() -> Result<()> {
+        let dir = write_conf_dir(
+            "include_cycle",
+            &[
+                (
+                    "main.dt",
+                    "include \"a.dt\"\nmenu root {\n a: \"echo a\"\n}",
+                ),
+                ("a.dt", "include \"b.dt\"\nmenu a {\n a: \"echo a\"\n}"),
+                ("b.dt", "include \"a.dt\"\nmenu b {\n b: \"echo b\"\n}"),
+            ],
+        )?;
+        let err = parse_file(&dir.path().join("main.dt")).unwrap_err();
+        k9::snapshot!(
+            err.to_string()
+                .replace(&dir.path().display().to_string(), "<dir>")
+                .lines()
+                .collect::<Vec<_>>(),
+            r#"
+[
+    " --> <dir>/b.dt:1:1",
+    "  |",
+    "1 | include "a.dt"",
+    "  | ^------------^",
+    "  |",
+    "  = include cycle: <dir>/a.dt -> <dir>/b.dt -> <dir>/a.dt",
+]
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn include_duplicate_menu() -> Result<()> {
+        let dir = write_conf_dir(
+            "include_duplicate",
+            &[
+                (
+                    "main.dt",
+                    "include \"a.dt\"\nmenu root {\n a: \"echo a\"\n}",
+                ),
+                ("a.dt", "menu root {\n b: \"echo b\"\n}"),
+            ],
+        )?;
+        let err = parse_file(&dir.path().join("main.dt")).unwrap_err();
+        k9::snapshot!(
+            err.to_string()
+                .replace(&dir.path().display().to_string(), "<dir>")
+                .lines()
+                .collect::<Vec<_>>(),
+            r#"
+[
+    " --> <dir>/a.dt:1:6",
+    "  |",
+    "1 | menu root {",
+    "  |      ^--^",
+    "  |",
+    "  = menu root is already defined in <dir>/main.dt",
+]
 "#
         );
         Ok(())
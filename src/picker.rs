@@ -0,0 +1,190 @@
+use anyhow::{anyhow, ensure, Result};
+use console::{style, truncate_str, Key, Term};
+use std::io;
+
+/// how many items are shown at once
+const MAX_SHOWN: usize = 10;
+
+/// Lets the user select one of `items` by typing a fuzzy filter and moving through the matches
+/// with the arrow keys. If `multi` is set, Tab toggles the selection of the highlighted item and
+/// all selected items are returned. The cursor starts at `initial`, if it is one of the items.
+pub fn pick(
+    term: &Term,
+    prompt: &str,
+    items: &[String],
+    multi: bool,
+    initial: Option<&str>,
+) -> Result<Vec<String>> {
+    let picked = pick_indices(term, prompt, items, multi, initial)?;
+    Ok(picked.into_iter().map(|i| items[i].clone()).collect())
+}
+
+/// Like [pick], but returns the indices of the selected items
+pub fn pick_indices(
+    term: &Term,
+    prompt: &str,
+    items: &[String],
+    multi: bool,
+    initial: Option<&str>,
+) -> Result<Vec<usize>> {
+    ensure!(!items.is_empty(), "there is nothing to select from");
+    let mut query = String::new();
+    let mut cursor = initial
+        .and_then(|initial| items.iter().position(|i| i == initial))
+        .unwrap_or(0);
+    let mut selected = vec![false; items.len()];
+    let mut n_lines = 0;
+
+    term.hide_cursor()?;
+    let result = loop {
+        let matches = filter(items, &query);
+        cursor = cursor.min(matches.len().saturating_sub(1));
+        term.clear_last_lines(n_lines)?;
+        n_lines = render(term, prompt, &query, items, &matches, &selected, cursor)?;
+
+        let key = match term.read_key() {
+            Ok(key) => key,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => break Err(anyhow!("aborted")),
+            Err(e) => break Err(e.into()),
+        };
+        match key {
+            Key::Escape => break Err(anyhow!("aborted")),
+            Key::Enter if multi && selected.contains(&true) => {
+                break Ok((0..items.len()).filter(|i| selected[*i]).collect());
+            }
+            Key::Enter if !matches.is_empty() => break Ok(vec![matches[cursor]]),
+            Key::Tab if multi && !matches.is_empty() => {
+                selected[matches[cursor]] ^= true;
+                cursor = (cursor + 1).min(matches.len() - 1);
+            }
+            Key::ArrowUp => cursor = cursor.saturating_sub(1),
+            Key::ArrowDown => cursor += 1,
+            Key::Backspace => {
+                query.pop();
+            }
+            Key::Char(c) if !c.is_control() => {
+                query.push(c);
+                cursor = 0;
+            }
+            _ => {}
+        }
+    };
+    term.clear_last_lines(n_lines)?;
+    term.show_cursor()?;
+    result
+}
+
+fn render(
+    term: &Term,
+    prompt: &str,
+    query: &str,
+    items: &[String],
+    matches: &[usize],
+    selected: &[bool],
+    cursor: usize,
+) -> Result<usize> {
+    let count = style(format!("({}/{})", matches.len(), items.len())).dim();
+    term.write_line(&format!("{prompt}: {query} {count}"))?;
+    let offset = cursor.saturating_sub(MAX_SHOWN - 1);
+    let shown = &matches[offset..matches.len().min(offset + MAX_SHOWN)];
+    // lines must not wrap, otherwise they can't be cleared correctly
+    let width = (term.size().1 as usize).saturating_sub(3);
+    for (i, idx) in shown.iter().enumerate() {
+        let marker = if selected[*idx] { "*" } else { " " };
+        let item = truncate_str(&items[*idx], width, "…");
+        let line = if offset + i == cursor {
+            format!("> {marker}{}", style(item).cyan().bold())
+        } else {
+            format!("  {marker}{item}")
+        };
+        term.write_line(&line)?;
+    }
+    Ok(shown.len() + 1)
+}
+
+/// Returns the indices of the items matching `query`, the best matches first
+fn filter(items: &[String], query: &str) -> Vec<usize> {
+    let mut matches: Vec<_> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| fuzzy_score(item, query).map(|score| (score, i)))
+        .collect();
+    // the sort is stable, so equally good matches keep their order
+    matches.sort_by_key(|(score, _)| *score);
+    matches.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Every char of the query has to appear in the item in the same order, ignoring case.
+/// The score is the number of chars skipped between the matched ones, so lower is better.
+fn fuzzy_score(item: &str, query: &str) -> Option<usize> {
+    let mut item_chars = item.chars().flat_map(char::to_lowercase);
+    let mut score = 0;
+    let mut started = false;
+    for q in query.chars().flat_map(char::to_lowercase) {
+        loop {
+            let c = item_chars.next()?;
+            if c == q {
+                started = true;
+                break;
+            }
+            if started {
+                score += 1;
+            }
+        }
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_filter() {
+        let items: Vec<_> = ["main", "feature/login", "fix/lint", "release-1.0"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let filtered = |query| {
+            filter(&items, query)
+                .into_iter()
+                .map(|i| items[i].as_str())
+                .collect::<Vec<_>>()
+        };
+        k9::snapshot!(
+            filtered(""),
+            r#"
+[
+    "main",
+    "feature/login",
+    "fix/lint",
+    "release-1.0",
+]
+"#
+        );
+        k9::snapshot!(
+            filtered("li"),
+            r#"
+[
+    "fix/lint",
+    "feature/login",
+]
+"#
+        );
+        k9::snapshot!(
+            filtered("FIN"),
+            r#"
+[
+    "fix/lint",
+    "feature/login",
+]
+"#
+        );
+        k9::snapshot!(
+            filtered("xyz"),
+            r#"
+[]
+"#
+        );
+    }
+}
@@ -1,23 +1,69 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use once_cell::sync::OnceCell;
 
 use crate::parser::ShellDef;
 
-static LOCAL_CONF_DIR: OnceCell<Option<PathBuf>> = OnceCell::new();
-static SHELL: OnceCell<ShellDef> = OnceCell::new();
+static RT_CONF: OnceCell<RtConf> = OnceCell::new();
 
-pub fn init(local_conf_dir: Option<PathBuf>, shell: ShellDef) {
-    LOCAL_CONF_DIR
-        .set(local_conf_dir)
-        .expect("initiating rt conf twice");
-    SHELL.set(shell).unwrap();
+/// The settings of this run of dt, as determined from the command line and the environment
+pub struct RtConf {
+    pub conf_path: PathBuf,
+    pub local_conf_dir: Option<PathBuf>,
+    pub shell: ShellDef,
+    pub assume_yes: bool,
+    pub dry_run: bool,
+    pub print_only: bool,
+    pub directive_file: Option<PathBuf>,
+    pub cursor_mode: bool,
+}
+
+pub fn init(rt_conf: RtConf) {
+    if RT_CONF.set(rt_conf).is_err() {
+        panic!("initiating rt conf twice");
+    }
+}
+
+fn get() -> &'static RtConf {
+    RT_CONF.get().expect("missing initiation")
+}
+
+/// The path of the config file in use
+pub fn conf_path() -> &'static Path {
+    &get().conf_path
 }
 
 pub fn local_conf_dir() -> Option<&'static PathBuf> {
-    LOCAL_CONF_DIR.get().expect("missing initiation").as_ref()
+    get().local_conf_dir.as_ref()
 }
 
 pub fn shell_def() -> &'static ShellDef {
-    SHELL.get().expect("missing initiation")
+    &get().shell
+}
+
+/// Whether to run without asking questions, e.g. use default values instead of querying variables
+pub fn assume_yes() -> bool {
+    get().assume_yes
+}
+
+/// Whether to print what would be executed instead of executing it
+pub fn dry_run() -> bool {
+    get().dry_run
+}
+
+/// Whether to print the command to stdout instead of executing it
+pub fn print_only() -> bool {
+    get().print_only
+}
+
+/// The file to write directives for the wrapper function of `dt shell-init` to, if dt was
+/// started through it
+pub fn directive_file() -> Option<&'static Path> {
+    get().directive_file.as_deref()
+}
+
+/// Whether an entry of the menu is highlighted, that can be moved with the arrow keys and
+/// activated with Enter
+pub fn cursor_mode() -> bool {
+    get().cursor_mode
 }
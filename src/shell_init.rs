@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The env var through which the wrapper function passes the path of the file that dt writes
+/// directives to. The wrapper sources the file, once dt is done
+pub const DIRECTIVE_FILE_VAR: &str = "DT_DIRECTIVE_FILE";
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum InitShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Returns the script that integrates dt into `shell`. It defines a wrapper function, that applies
+/// the directives of `set cd` and `set export` commands to the shell, and a widget that runs
+/// `dt --print` and inserts the printed command at the cursor, bound to Alt+o
+pub fn script(shell: InitShell) -> &'static str {
+    match shell {
+        InitShell::Bash => BASH,
+        InitShell::Zsh => ZSH,
+        InitShell::Fish => FISH,
+    }
+}
+
+/// A change of the calling shell, that the wrapper function applies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Directive {
+    Cd(PathBuf),
+    Export(String, String),
+}
+
+/// Appends the directives to the file the wrapper function sources. They are written in a syntax
+/// that bash, zsh and fish understand alike
+pub fn emit(file: &Path, directives: &[Directive]) -> Result<()> {
+    let mut content = String::new();
+    for directive in directives {
+        match directive {
+            Directive::Cd(dir) => {
+                content += &format!("cd {}\n", quote(&dir.to_string_lossy()));
+            }
+            Directive::Export(name, val) => {
+                content += &format!("export {name}={}\n", quote(val));
+            }
+        }
+    }
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(file)
+        .and_then(|mut f| f.write_all(content.as_bytes()))
+        .context("writing directive file")
+}
+
+/// Quotes `val` with single quotes. Backslashes are escaped outside of them, since fish treats
+/// them as escape characters within single quotes as well
+pub(crate) fn quote(val: &str) -> String {
+    let mut res = String::from("'");
+    for c in val.chars() {
+        match c {
+            '\'' => res.push_str(r"'\''"),
+            '\\' => res.push_str(r"'\\'"),
+            c => res.push(c),
+        }
+    }
+    res.push('\'');
+    res
+}
+
+const BASH: &str = r#"dt() {
+    local directive ret
+    directive="$(mktemp)" || return
+    DT_DIRECTIVE_FILE="$directive" command dt "$@"
+    ret=$?
+    [ -s "$directive" ] && . "$directive"
+    rm -f "$directive"
+    return $ret
+}
+__dt_widget() {
+    local cmd
+    cmd="$(command dt --print)" || return
+    READLINE_LINE="${READLINE_LINE:0:READLINE_POINT}${cmd}${READLINE_LINE:READLINE_POINT}"
+    READLINE_POINT=$((READLINE_POINT + ${#cmd}))
+}
+bind -m emacs-standard -x '"\eo": __dt_widget'
+bind -m vi-insert -x '"\eo": __dt_widget'
+"#;
+
+const ZSH: &str = r#"dt() {
+    local directive ret
+    directive="$(mktemp)" || return
+    DT_DIRECTIVE_FILE="$directive" command dt "$@"
+    ret=$?
+    [ -s "$directive" ] && . "$directive"
+    rm -f "$directive"
+    return $ret
+}
+dt-widget() {
+    local cmd
+    cmd="$(command dt --print < /dev/tty)"
+    LBUFFER="${LBUFFER}${cmd}"
+    zle reset-prompt
+}
+zle -N dt-widget
+bindkey -M emacs '\eo' dt-widget
+bindkey -M viins '\eo' dt-widget
+"#;
+
+const FISH: &str = r#"function dt --wraps dt
+    set -l directive (mktemp)
+    or return
+    DT_DIRECTIVE_FILE=$directive command dt $argv
+    set -l ret $status
+    test -s $directive
+    and source $directive
+    rm -f $directive
+    return $ret
+end
+function dt-widget
+    set -l cmd (command dt --print | string collect)
+    and commandline -i -- $cmd
+    commandline -f repaint
+end
+bind \eo dt-widget
+if bind -M insert >/dev/null 2>&1
+    bind -M insert \eo dt-widget
+end
+"#;
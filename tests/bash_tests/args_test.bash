@@ -0,0 +1,2 @@
+$DT -c args_test.dt p hello world
+$DT -c args_test.dt t "it's" "a \$test"
@@ -0,0 +1,2 @@
+$DT check -c check_test.dt
+echo "exit code: $?"
@@ -0,0 +1 @@
+$DT -c confirm_test.dt -y p
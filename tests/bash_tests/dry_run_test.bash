@@ -0,0 +1,2 @@
+$DT -c dry_run_test.dt --dry-run gw feature /tmp/x
+$DT -c dry_run_test.dt --dry-run gp world -- --verbose
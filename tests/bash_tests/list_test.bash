@@ -0,0 +1,3 @@
+$DT -c list_test.dt list
+$DT -c list_test.dt which gw
+$DT -c list_test.dt which grmp
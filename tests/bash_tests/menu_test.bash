@@ -0,0 +1,8 @@
+$DT -c menu_test.dt -m git w main
+$DT -c menu_test.dt --menu volume u
+$DT -c menu_test.dt -m git list
+$DT -c menu_test.dt -m nothing s
+$DT check -c menu_test.dt
+echo "exit code: $?"
+$DT check -c menu_test_broken.dt 2>&1
+echo "exit code: $?"
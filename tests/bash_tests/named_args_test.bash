@@ -0,0 +1,6 @@
+$DT -c named_args_test.dt -y w --output-dir /src main
+$DT -c named_args_test.dt -y w --branch=dev -- --release "two words"
+$DT -c named_args_test.dt p first -- --verbose
+$DT -c named_args_test.dt m world -- a "b c"
+echo
+$DT -c named_args_test.dt c world -- a 2>&1 | head -n 1
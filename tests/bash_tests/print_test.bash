@@ -0,0 +1,6 @@
+$DT -c print_test.dt --print w "my feature" /tmp/x 2>/dev/null
+$DT -c print_test.dt --print p "it's" -- --verbose 2>/dev/null
+$DT -c print_test.dt --print y world 2>/dev/null
+$DT -c print_test.dt --print c 'back\slash' 2>/dev/null
+eval "$($DT -c print_test.dt --print c world 2>/dev/null)"
+echo "name in this shell: ${name:-unset}"
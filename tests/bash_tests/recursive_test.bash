@@ -0,0 +1 @@
+$DT -c recursive_test.dt srsrse
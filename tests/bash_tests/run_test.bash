@@ -0,0 +1,5 @@
+$DT -c run_test.dt run git/amend_all
+$DT -c run_test.dt run git.add_worktree main /tmp/x
+$DT -c run_test.dt run git/add_worktree --dir /src dev
+$DT -c run_test.dt which gw
+$DT -c run_test.dt gw main /tmp/y
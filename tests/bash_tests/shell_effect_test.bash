@@ -0,0 +1,11 @@
+# the wrapper function calls dt from the PATH
+PATH="$(dirname "$DT"):$PATH"
+eval "$($DT shell-init bash)" 2>/dev/null
+test_dir="$PWD"
+cd /
+dt -c "$test_dir/shell_effect_test.dt" c usr
+pwd
+cd "$test_dir"
+dt -c shell_effect_test.dt e "hello world"
+echo "$GREETING"
+echo "$OTHER"
@@ -0,0 +1,5 @@
+export DT_TEST_NAME=world
+$DT -c var_defaults_test.dt -y g
+$DT -c var_defaults_test.dt -y g hi
+$DT g -y -c var_defaults_test.dt
+$DT -c var_defaults_test.dt g hi --yes
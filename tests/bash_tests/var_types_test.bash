@@ -0,0 +1,2 @@
+$DT -c var_types_test.dt -y d prod
+$DT -c var_types_test.dt -y d dev " 12" yes